@@ -0,0 +1,601 @@
+// src/admission.rs
+
+use crate::{config::ConcurrencyConfig, models::glob_match};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+#[derive(Debug, Error)]
+pub enum AdmissionError {
+    #[error("invalid queue timeout '{0}': {1}")]
+    Timeout(String, humantime::DurationError),
+    #[error("unknown priority class '{0}'")]
+    UnknownClass(String),
+}
+
+/// Why a request was not admitted.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum Refusal {
+    #[error("too many requests waiting for capacity")]
+    QueueFull,
+    #[error("timed out waiting for capacity")]
+    TimedOut,
+}
+
+impl Refusal {
+    /// Label for the policy rejection counter.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Refusal::QueueFull => "queue_full",
+            Refusal::TimedOut => "queue_timeout",
+        }
+    }
+}
+
+/// A limited resource a request occupies: `(key, limit)`.
+type Slot = (String, usize);
+
+struct Waiter {
+    id: u64,
+    slots: Vec<Slot>,
+    tx: oneshot::Sender<Permit>,
+}
+
+/// Waiters of one class by tenant, FIFO within a tenant and served
+/// round-robin across tenants.
+type ClassQueue = VecDeque<(String, VecDeque<Waiter>)>;
+
+#[derive(Default)]
+struct Slots {
+    in_flight: HashMap<String, usize>,
+    /// In-flight requests per slot and class, for reservations
+    class_in_flight: HashMap<(String, usize), usize>,
+    /// One queue per class, highest first
+    queues: Vec<ClassQueue>,
+    reserved: Vec<usize>,
+    queued: usize,
+    next_id: u64,
+}
+
+impl Slots {
+    fn fits(&self, slots: &[Slot]) -> bool {
+        slots
+            .iter()
+            .all(|(k, limit)| self.in_flight.get(k).copied().unwrap_or(0) < *limit)
+    }
+
+    /// `class` uses less than its reservation of every slot.
+    fn under_reservation(&self, slots: &[Slot], class: usize) -> bool {
+        let reserved = self.reserved[class];
+        reserved > 0
+            && slots.iter().all(|(k, _)| {
+                self.class_in_flight
+                    .get(&(k.clone(), class))
+                    .copied()
+                    .unwrap_or(0)
+                    < reserved
+            })
+    }
+
+    fn take(&mut self, slots: &[Slot], class: usize) {
+        for (k, _) in slots {
+            *self.in_flight.entry(k.clone()).or_insert(0) += 1;
+            *self.class_in_flight.entry((k.clone(), class)).or_insert(0) += 1;
+        }
+    }
+
+    fn release(&mut self, keys: &[String], class: usize) {
+        for k in keys {
+            decrement(&mut self.in_flight, k.clone());
+            decrement(&mut self.class_in_flight, (k.clone(), class));
+        }
+    }
+
+    /// Waiters that go before a new `class` request for `slots`: those of
+    /// the same or a higher class, and lower ones owed their reservation.
+    fn ahead(&self, slots: &[Slot], class: usize) -> usize {
+        let shares = |w: &Waiter| {
+            w.slots
+                .iter()
+                .any(|(k, _)| slots.iter().any(|(s, _)| s == k))
+        };
+        self.queues
+            .iter()
+            .enumerate()
+            .flat_map(|(c, q)| q.iter().flat_map(|(_, w)| w).map(move |w| (c, w)))
+            .filter(|(c, w)| shares(w) && (*c <= class || self.under_reservation(&w.slots, *c)))
+            .count()
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        for queues in &mut self.queues {
+            for i in 0..queues.len() {
+                let queue = &mut queues[i].1;
+                if let Some(pos) = queue.iter().position(|w| w.id == id) {
+                    queue.remove(pos);
+                    if queue.is_empty() {
+                        queues.remove(i);
+                    }
+                    self.queued -= 1;
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Take the first waiter of `class` that fits and passes `eligible`,
+    /// trying each tenant in turn. The tenant served moves to the back.
+    fn pick(&mut self, class: usize, eligible: impl Fn(&Self, &Waiter) -> bool) -> Option<Waiter> {
+        for _ in 0..self.queues[class].len() {
+            let (tenant, mut queue) = self.queues[class].pop_front()?;
+            let pos = queue
+                .iter()
+                .position(|w| self.fits(&w.slots) && eligible(self, w));
+            let picked = pos.and_then(|p| queue.remove(p));
+            if !queue.is_empty() {
+                self.queues[class].push_back((tenant, queue));
+            }
+            if picked.is_some() {
+                return picked;
+            }
+        }
+        None
+    }
+
+    /// Hand freed capacity to waiters: first to classes below their
+    /// reservation, then by class. Returns the permits whose waiter has
+    /// gone away; they must be dropped after the lock is released.
+    fn dispatch(&mut self, shared: &Arc<Mutex<Slots>>) -> Vec<Permit> {
+        let mut undelivered = Vec::new();
+        loop {
+            let classes = 0..self.queues.len();
+            let reserved = classes.clone().find_map(|c| {
+                self.pick(c, move |s, w| s.under_reservation(&w.slots, c))
+                    .map(|w| (c, w))
+            });
+            let Some((class, w)) = reserved.or_else(|| {
+                classes
+                    .clone()
+                    .find_map(|c| Some((c, self.pick(c, |_, _| true)?)))
+            }) else {
+                break;
+            };
+            self.queued -= 1;
+            self.take(&w.slots, class);
+            if let Err(p) = w.tx.send(Permit::new(&w.slots, class, shared)) {
+                undelivered.push(p);
+            }
+        }
+        undelivered
+    }
+}
+
+fn decrement<K: std::hash::Hash + Eq>(map: &mut HashMap<K, usize>, key: K) {
+    if let Some(n) = map.get_mut(&key) {
+        *n -= 1;
+        if *n == 0 {
+            map.remove(&key);
+        }
+    }
+}
+
+/// Limits how many generation and embedding requests run at once, per
+/// model and per upstream. Requests over the limit wait in a bounded queue
+/// that serves higher priority classes first and is fair across tenants
+/// within a class.
+pub struct Admission {
+    models: Vec<(String, usize)>,
+    per_upstream: Option<usize>,
+    upstreams: HashMap<String, usize>,
+    max_queue: usize,
+    timeout: Duration,
+    classes: Vec<String>,
+    default_class: usize,
+    tenants: HashMap<String, usize>,
+    priority_header: Option<String>,
+    slots: Arc<Mutex<Slots>>,
+}
+
+/// The outcome of [`Admission::admit`].
+pub enum Admit {
+    Now(Permit),
+    Queued(Ticket),
+}
+
+impl Admission {
+    pub fn new(cfg: &ConcurrencyConfig) -> Result<Self, AdmissionError> {
+        let timeout = humantime::parse_duration(&cfg.queue_timeout)
+            .map_err(|e| AdmissionError::Timeout(cfg.queue_timeout.clone(), e))?;
+        let mut classes: Vec<String> = cfg.classes.iter().map(|c| c.name.clone()).collect();
+        let mut reserved: Vec<usize> = cfg.classes.iter().map(|c| c.reserved).collect();
+        if classes.is_empty() {
+            classes.push("default".to_string());
+            reserved.push(0);
+        }
+        let index = |name: &str| {
+            classes
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| AdmissionError::UnknownClass(name.to_string()))
+        };
+        let default_class = cfg.default_class.as_deref().map_or(Ok(0), index)?;
+        let tenants = cfg
+            .tenants
+            .iter()
+            .map(|(t, c)| Ok((t.clone(), index(c)?)))
+            .collect::<Result<_, AdmissionError>>()?;
+        let slots = Slots {
+            queues: classes.iter().map(|_| ClassQueue::new()).collect(),
+            reserved,
+            ..Slots::default()
+        };
+        Ok(Self {
+            models: cfg
+                .models
+                .iter()
+                .flat_map(|l| l.models.iter().map(|m| (m.clone(), l.max_in_flight)))
+                .collect(),
+            per_upstream: cfg.per_upstream,
+            upstreams: cfg
+                .upstreams
+                .iter()
+                .map(|(u, n)| (u.trim_end_matches('/').to_string(), *n))
+                .collect(),
+            max_queue: cfg.max_queue,
+            timeout,
+            classes,
+            default_class,
+            tenants,
+            priority_header: cfg.priority_header.clone(),
+            slots: Arc::new(Mutex::new(slots)),
+        })
+    }
+
+    /// Seconds a refused client should wait before retrying.
+    pub fn retry_after_secs(&self) -> u64 {
+        self.timeout.as_secs().max(1)
+    }
+
+    pub fn priority_header(&self) -> Option<&str> {
+        self.priority_header.as_deref()
+    }
+
+    pub fn class_name(&self, class: usize) -> &str {
+        &self.classes[class]
+    }
+
+    /// The caller's class: the key's own `priority`, else the tenant's,
+    /// else the default. A `requested` class is honoured only if it is not
+    /// higher than that.
+    pub fn class_for(
+        &self,
+        tenant: &str,
+        assigned: Option<&str>,
+        requested: Option<&str>,
+    ) -> usize {
+        let index = |name: &str| self.classes.iter().position(|c| c == name);
+        let own = assigned
+            .and_then(index)
+            .or_else(|| self.tenants.get(tenant).copied())
+            .unwrap_or(self.default_class);
+        requested
+            .and_then(index)
+            .filter(|r| *r >= own)
+            .unwrap_or(own)
+    }
+
+    fn slots_for(&self, model: &str, upstream: &str) -> Vec<Slot> {
+        let mut slots = Vec::new();
+        if let Some((_, n)) = self.models.iter().find(|(p, _)| glob_match(p, model)) {
+            slots.push((format!("model:{}", model), *n));
+        }
+        let upstream = upstream.trim_end_matches('/');
+        if let Some(n) = self.upstreams.get(upstream).copied().or(self.per_upstream) {
+            slots.push((format!("upstream:{}", upstream), n));
+        }
+        slots
+    }
+
+    /// Take a slot for `model` on `upstream`, or join the queue for one.
+    pub fn admit(
+        &self,
+        tenant: &str,
+        class: usize,
+        model: &str,
+        upstream: &str,
+    ) -> Result<Admit, Refusal> {
+        let wanted = self.slots_for(model, upstream);
+        let mut s = self.slots.lock();
+        let position = s.ahead(&wanted, class);
+        if s.fits(&wanted) && position == 0 {
+            s.take(&wanted, class);
+            return Ok(Admit::Now(Permit::new(&wanted, class, &self.slots)));
+        }
+        if s.queued >= self.max_queue {
+            return Err(Refusal::QueueFull);
+        }
+        let (tx, rx) = oneshot::channel();
+        let id = s.next_id;
+        s.next_id += 1;
+        let waiter = Waiter {
+            id,
+            slots: wanted,
+            tx,
+        };
+        let queues = &mut s.queues[class];
+        match queues.iter_mut().find(|(t, _)| t == tenant) {
+            Some((_, q)) => q.push_back(waiter),
+            None => queues.push_back((tenant.to_string(), VecDeque::from([waiter]))),
+        }
+        s.queued += 1;
+        Ok(Admit::Queued(Ticket {
+            id,
+            position,
+            rx,
+            timeout: self.timeout,
+            slots: self.slots.clone(),
+        }))
+    }
+
+    /// Move a running request's permit to `upstream`, as when a retry fails
+    /// over, if a slot there is free and nobody waits for it. Slots the
+    /// request already holds carry over. On `false` the permit is unchanged.
+    pub fn transfer(&self, permit: &mut Permit, model: &str, upstream: &str) -> bool {
+        let wanted = self.slots_for(model, upstream);
+        let added: Vec<Slot> = wanted
+            .iter()
+            .filter(|(k, _)| !permit.keys.contains(k))
+            .cloned()
+            .collect();
+        let dropped: Vec<String> = permit
+            .keys
+            .iter()
+            .filter(|k| !wanted.iter().any(|(w, _)| w == *k))
+            .cloned()
+            .collect();
+        let undelivered = {
+            let mut s = self.slots.lock();
+            if !s.fits(&added) || s.ahead(&added, permit.class) > 0 {
+                return false;
+            }
+            s.take(&added, permit.class);
+            s.release(&dropped, permit.class);
+            s.dispatch(&self.slots)
+        };
+        permit.keys = wanted.into_iter().map(|(k, _)| k).collect();
+        drop(undelivered);
+        true
+    }
+
+    /// Carry over the slots of an admission being replaced, so requests
+    /// running or queued under the old limits still count. Waiters keep the
+    /// limits they queued with; those of a class that no longer exists move
+    /// to the lowest one.
+    pub fn take_slots(&mut self, old: &Admission) {
+        let reserved = std::mem::take(&mut self.slots.lock().reserved);
+        self.slots = old.slots.clone();
+        let undelivered = {
+            let mut s = self.slots.lock();
+            let lowest = reserved.len() - 1;
+            while s.queues.len() > lowest + 1 {
+                let dropped = s.queues.pop().unwrap_or_default();
+                for (tenant, waiters) in dropped {
+                    let queue = &mut s.queues[lowest];
+                    match queue.iter_mut().find(|(t, _)| *t == tenant) {
+                        Some((_, q)) => q.extend(waiters),
+                        None => queue.push_back((tenant, waiters)),
+                    }
+                }
+            }
+            s.queues.resize_with(reserved.len(), ClassQueue::new);
+            s.reserved = reserved;
+            s.dispatch(&self.slots)
+        };
+        drop(undelivered);
+    }
+}
+
+/// A place in the queue. Dropping it gives the place up.
+pub struct Ticket {
+    id: u64,
+    position: usize,
+    rx: oneshot::Receiver<Permit>,
+    timeout: Duration,
+    slots: Arc<Mutex<Slots>>,
+}
+
+impl Ticket {
+    /// Waiters ahead of this one when it joined the queue.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub async fn wait(mut self) -> Result<Permit, Refusal> {
+        match tokio::time::timeout(self.timeout, &mut self.rx).await {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => {
+                if self.slots.lock().remove(self.id) {
+                    return Err(Refusal::TimedOut);
+                }
+                // Granted just as the wait ran out.
+                self.rx.try_recv().map_err(|_| Refusal::TimedOut)
+            }
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        self.slots.lock().remove(self.id);
+    }
+}
+
+/// Held while the request runs; frees its slots when dropped.
+pub struct Permit {
+    keys: Vec<String>,
+    class: usize,
+    slots: Arc<Mutex<Slots>>,
+}
+
+impl Permit {
+    fn new(slots: &[Slot], class: usize, shared: &Arc<Mutex<Slots>>) -> Self {
+        Self {
+            keys: slots.iter().map(|(k, _)| k.clone()).collect(),
+            class,
+            slots: shared.clone(),
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let undelivered = {
+            let mut s = self.slots.lock();
+            s.release(&self.keys, self.class);
+            s.dispatch(&self.slots)
+        };
+        drop(undelivered);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admission(yaml: &str) -> Admission {
+        Admission::new(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    fn now(a: Admit) -> Permit {
+        match a {
+            Admit::Now(p) => p,
+            Admit::Queued(_) => panic!("queued"),
+        }
+    }
+
+    fn queued(a: Admit) -> Ticket {
+        match a {
+            Admit::Now(_) => panic!("admitted"),
+            Admit::Queued(t) => t,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_limits_and_fair_queue() {
+        let a = admission(
+            "{ models: [{ models: ['llama3*'], max_in_flight: 1 }], per_upstream: 2,
+               max_queue: 3, queue_timeout: 50ms }",
+        );
+        let up = "http://gpu-a:11434";
+        let first = now(a.admit("t1", 0, "llama3.1", up).unwrap());
+        // Unlimited model, but the upstream still has room for one.
+        let other = now(a.admit("t1", 0, "mistral", up).unwrap());
+
+        // t1 queues twice before t2 arrives; t2 is still served second.
+        let t1a = queued(a.admit("t1", 0, "llama3.1", up).unwrap());
+        let t1b = queued(a.admit("t1", 0, "llama3.1", up).unwrap());
+        let t2 = queued(a.admit("t2", 0, "llama3.1", up).unwrap());
+        assert_eq!(
+            a.admit("t3", 0, "llama3.1", up).err(),
+            Some(Refusal::QueueFull)
+        );
+
+        drop(other);
+        drop(first);
+        let p = t1a.wait().await.unwrap();
+        drop(p);
+        let p = t2.wait().await.unwrap();
+        assert_eq!(t1b.wait().await.err(), Some(Refusal::TimedOut));
+        drop(p);
+        assert!(a.slots.lock().in_flight.is_empty());
+        assert_eq!(a.slots.lock().queued, 0);
+
+        // A cancelled waiter gives up its place.
+        let _busy = now(a.admit("t1", 0, "llama3.1", up).unwrap());
+        drop(queued(a.admit("t1", 0, "llama3.1", up).unwrap()));
+        assert_eq!(a.slots.lock().queued, 0);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_to_another_upstream() {
+        let a = admission(
+            "{ models: [{ models: ['llama3*'], max_in_flight: 2 }], per_upstream: 1,
+               queue_timeout: 1s }",
+        );
+        let (gpu_a, gpu_b) = ("http://gpu-a:11434", "http://gpu-b:11434");
+        let mut p = now(a.admit("t1", 0, "llama3.1", gpu_a).unwrap());
+        let busy = now(a.admit("t1", 0, "mistral", gpu_b).unwrap());
+        assert!(!a.transfer(&mut p, "llama3.1", gpu_b));
+
+        // Moving frees gpu-a for the waiter; the model slot carries over.
+        let waiter = queued(a.admit("t2", 0, "llama3.1", gpu_a).unwrap());
+        drop(busy);
+        assert!(a.transfer(&mut p, "llama3.1", gpu_b));
+        let w = waiter.wait().await.unwrap();
+        assert_eq!(a.slots.lock().in_flight["model:llama3.1"], 2);
+        drop((p, w));
+        assert!(a.slots.lock().in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_take_slots_keeps_running_requests() {
+        let old = admission("{ per_upstream: 1, queue_timeout: 1s }");
+        let up = "http://gpu-a:11434";
+        let running = now(old.admit("t1", 0, "llama3.1", up).unwrap());
+
+        // The raised limit counts the request still running under the old one.
+        let mut new = admission("{ per_upstream: 2, queue_timeout: 1s }");
+        new.take_slots(&old);
+        let second = now(new.admit("t2", 0, "llama3.1", up).unwrap());
+        let third = queued(new.admit("t2", 0, "llama3.1", up).unwrap());
+        drop(running);
+        let p = third.wait().await.unwrap();
+        assert_eq!(new.slots.lock().in_flight["upstream:http://gpu-a:11434"], 2);
+        drop((p, second));
+        assert!(new.slots.lock().in_flight.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_priority_classes_and_reservation() {
+        let a = admission(
+            "{ per_upstream: 2, queue_timeout: 1s, default_class: standard,
+               classes: [{ name: interactive }, { name: standard }, { name: batch, reserved: 1 }],
+               tenants: { nightly: batch } }",
+        );
+        assert_eq!(a.class_for("web", None, None), 1);
+        assert_eq!(a.class_for("nightly", None, None), 2);
+        assert_eq!(a.class_for("nightly", Some("interactive"), None), 0);
+        // A header may lower the class, never raise it.
+        assert_eq!(a.class_for("web", None, Some("batch")), 2);
+        assert_eq!(a.class_for("web", None, Some("interactive")), 1);
+
+        let up = "http://gpu-a:11434";
+        let admit = |class| a.admit("t", class, "m", up).unwrap();
+        let s1 = now(admit(1));
+        let s2 = now(admit(1));
+        let batch = queued(admit(2));
+        let standard = queued(admit(1));
+        let interactive = queued(admit(0));
+        assert_eq!(interactive.position(), 1);
+
+        // Batch has nothing in flight, so its reservation goes first; then
+        // interactive overtakes standard.
+        drop(s1);
+        let b = batch.wait().await.unwrap();
+        drop(s2);
+        let i = interactive.wait().await.unwrap();
+        drop(b);
+        let _st = standard.wait().await.unwrap();
+        drop(i);
+        let _ = now(admit(2));
+    }
+}
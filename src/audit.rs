@@ -0,0 +1,344 @@
+// src/audit.rs
+
+use crate::config::{AuditBodyMode, AuditConfig};
+use log::error;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("failed to open audit log '{path}': {source}")]
+    Io { path: String, source: io::Error },
+    #[error("invalid audit rotate_interval '{0}': {1}")]
+    Interval(String, humantime::DurationError),
+}
+
+/// One line of the audit log.
+#[derive(Debug, Default, Serialize)]
+pub struct AuditRecord {
+    pub ts: String,
+    pub request_id: String,
+    pub tenant: Option<String>,
+    pub subject: Option<String>,
+    pub client_ip: String,
+    pub method: String,
+    pub endpoint: String,
+    pub model: Option<String>,
+    pub prompt_chars: usize,
+    /// `allowed`, or the policy rule that refused the request
+    pub decision: String,
+    /// DLP patterns found in the prompt
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dlp: Vec<String>,
+    /// Content rules that matched, as `name:action`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+    /// Status returned to the client
+    pub status: u16,
+    pub upstream: Option<String>,
+    pub upstream_status: Option<u16>,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+}
+
+/// Records a body as it streams past, according to an [`AuditBodyMode`].
+pub struct BodyCapture {
+    mode: AuditBodyMode,
+    limit: usize,
+    hasher: Sha256,
+    head: Vec<u8>,
+    len: u64,
+}
+
+impl BodyCapture {
+    /// `None` when the mode is `off`.
+    pub fn new(mode: AuditBodyMode, truncate_chars: usize) -> Option<Self> {
+        (mode != AuditBodyMode::Off).then(|| Self {
+            mode,
+            limit: truncate_chars,
+            hasher: Sha256::new(),
+            head: Vec::new(),
+            len: 0,
+        })
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.len += chunk.len() as u64;
+        match self.mode {
+            AuditBodyMode::Hash => self.hasher.update(chunk),
+            AuditBodyMode::Truncate => {
+                // A char is at most 4 bytes; trimmed to `limit` chars in finish.
+                let room = (self.limit * 4).saturating_sub(self.head.len());
+                self.head.extend_from_slice(&chunk[..room.min(chunk.len())]);
+            }
+            AuditBodyMode::Redact | AuditBodyMode::Off => {}
+        }
+    }
+
+    pub fn finish(self) -> String {
+        match self.mode {
+            AuditBodyMode::Hash => format!("sha256:{}", hex::encode(self.hasher.finalize())),
+            AuditBodyMode::Truncate => String::from_utf8_lossy(&self.head)
+                .chars()
+                .take(self.limit)
+                .collect(),
+            AuditBodyMode::Redact | AuditBodyMode::Off => {
+                format!("[redacted {} bytes]", self.len)
+            }
+        }
+    }
+}
+
+/// Appends [`AuditRecord`]s as JSON lines from a background thread so
+/// request handlers never block on disk I/O.
+pub struct AuditLog {
+    prompt: AuditBodyMode,
+    response: AuditBodyMode,
+    truncate_chars: usize,
+    tx: Option<mpsc::Sender<String>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl AuditLog {
+    pub fn open(cfg: &AuditConfig) -> Result<Self, AuditError> {
+        let rotate_interval = cfg
+            .rotate_interval
+            .as_deref()
+            .map(|s| humantime::parse_duration(s).map_err(|e| AuditError::Interval(s.into(), e)))
+            .transpose()?;
+        let mut file = RotatingFile::open(
+            PathBuf::from(&cfg.path),
+            cfg.rotate_bytes,
+            rotate_interval,
+            cfg.keep_files,
+        )
+        .map_err(|source| AuditError::Io {
+            path: cfg.path.clone(),
+            source,
+        })?;
+        let (tx, rx) = mpsc::channel::<String>();
+        let writer = thread::Builder::new()
+            .name("audit-log".into())
+            .spawn(move || {
+                for line in rx {
+                    if let Err(e) = file.write_line(&line) {
+                        error!("audit log write to {} failed: {}", file.path.display(), e);
+                    }
+                }
+            })
+            .map_err(|source| AuditError::Io {
+                path: cfg.path.clone(),
+                source,
+            })?;
+        Ok(Self {
+            prompt: cfg.prompt,
+            response: cfg.response,
+            truncate_chars: cfg.truncate_chars,
+            tx: Some(tx),
+            writer: Some(writer),
+        })
+    }
+
+    /// The prompt as it should appear in the record, if at all.
+    pub fn render_prompt(&self, prompt: &str) -> Option<String> {
+        let mut capture = BodyCapture::new(self.prompt, self.truncate_chars)?;
+        capture.feed(prompt.as_bytes());
+        Some(capture.finish())
+    }
+
+    /// A capture for the response body, if responses are recorded.
+    pub fn response_capture(&self) -> Option<BodyCapture> {
+        BodyCapture::new(self.response, self.truncate_chars)
+    }
+
+    pub fn log(&self, mut record: AuditRecord) {
+        record.ts = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        match serde_json::to_string(&record) {
+            Ok(line) => {
+                if let Some(tx) = &self.tx {
+                    let _ = tx.send(line);
+                }
+            }
+            Err(e) => error!("failed to encode audit record: {}", e),
+        }
+    }
+}
+
+impl Drop for AuditLog {
+    /// Flush outstanding records before the writer goes away.
+    fn drop(&mut self) {
+        self.tx.take();
+        if let Some(w) = self.writer.take() {
+            let _ = w.join();
+        }
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened: Instant,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    keep: Option<usize>,
+}
+
+impl RotatingFile {
+    fn open(
+        path: PathBuf,
+        max_bytes: Option<u64>,
+        max_age: Option<Duration>,
+        keep: Option<usize>,
+    ) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            opened: Instant::now(),
+            max_bytes,
+            max_age,
+            keep,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        let too_big = self
+            .max_bytes
+            .is_some_and(|max| self.size > 0 && self.size + len > max);
+        let too_old = self.max_age.is_some_and(|age| self.opened.elapsed() >= age);
+        if too_big || too_old {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.file.flush()?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Move the current file to `<path>.<UTC timestamp>` and start a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        let stamp: String = humantime::format_rfc3339_seconds(SystemTime::now())
+            .to_string()
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect();
+        let base = format!("{}.{}", self.path.display(), stamp);
+        let mut target = PathBuf::from(&base);
+        let mut n = 1;
+        while target.exists() {
+            target = PathBuf::from(format!("{}.{}", base, n));
+            n += 1;
+        }
+        fs::rename(&self.path, &target)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+        if let Some(keep) = self.keep {
+            prune_rotated(&self.path, keep)?;
+        }
+        Ok(())
+    }
+}
+
+/// Delete all but the newest `keep` rotated siblings of `path`.
+fn prune_rotated(path: &Path, keep: usize) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|e| e.path())
+        .collect();
+    rotated.sort();
+    let excess = rotated.len().saturating_sub(keep);
+    for old in &rotated[..excess] {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction_modes_and_size_rotation() {
+        let dir = std::env::temp_dir().join(format!("gamb-audit-{}", std::process::id()));
+        let path = dir.join("audit.jsonl");
+        let cfg = AuditConfig {
+            path: path.display().to_string(),
+            prompt: AuditBodyMode::Truncate,
+            response: AuditBodyMode::Hash,
+            truncate_chars: 4,
+            rotate_bytes: Some(300),
+            rotate_interval: None,
+            keep_files: Some(1),
+        };
+        let log = AuditLog::open(&cfg).unwrap();
+        assert_eq!(log.render_prompt("héllo world").as_deref(), Some("héll"));
+        let mut capture = log.response_capture().unwrap();
+        capture.feed(b"ab");
+        capture.feed(b"c");
+        assert_eq!(
+            capture.finish(),
+            format!("sha256:{}", hex::encode(Sha256::digest(b"abc")))
+        );
+        let mut redact = BodyCapture::new(AuditBodyMode::Redact, 0).unwrap();
+        redact.feed(b"secret");
+        assert_eq!(redact.finish(), "[redacted 6 bytes]");
+        assert!(BodyCapture::new(AuditBodyMode::Off, 10).is_none());
+
+        for i in 0..6 {
+            log.log(AuditRecord {
+                request_id: format!("req-{}", i),
+                decision: "allowed".into(),
+                ..AuditRecord::default()
+            });
+        }
+        drop(log);
+
+        let current = fs::read_to_string(&path).unwrap();
+        let files: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+        fs::remove_dir_all(&dir).unwrap();
+        // Every record went somewhere, only one rotated file was kept.
+        assert_eq!(files.len(), 2);
+        let last: serde_json::Value =
+            serde_json::from_str(current.lines().last().unwrap()).unwrap();
+        assert_eq!(last["request_id"], "req-5");
+        assert_eq!(last["decision"], "allowed");
+        assert!(last["ts"].as_str().unwrap().ends_with('Z'));
+        assert!(last.get("prompt").is_none());
+    }
+}
@@ -0,0 +1,179 @@
+// src/auth.rs
+
+use crate::{
+    cf_access::CfAccessVerifier,
+    config::Auth,
+    jwks::{looks_like_jwt, JwtError},
+    key_store::{ApiKey, KeyRejection, KeyStore},
+    oidc::OidcVerifier,
+};
+use hyper::{header::AUTHORIZATION, HeaderMap};
+use reqwest::Client as ReqwestClient;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// How a request proved who it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMethod {
+    /// No credentials configured, so none required
+    #[default]
+    Anonymous,
+    /// Matched the shared `bearer_token`
+    StaticToken,
+    /// Matched a tenant key in the key store
+    ApiKey,
+    /// Bearer JWT verified against an OIDC provider
+    Oidc,
+    /// Only a Cloudflare Access assertion was presented
+    CloudflareAccess,
+}
+
+/// Caller identity handed to policy and logging stages.
+#[derive(Debug, Clone, Default)]
+pub struct Identity {
+    pub method: AuthMethod,
+    pub provider: Option<String>,
+    pub issuer: Option<String>,
+    pub subject: Option<String>,
+    pub email: Option<String>,
+    /// Email from a verified Cloudflare Access assertion
+    pub access_email: Option<String>,
+    /// Key store entry the request authenticated with
+    pub api_key: Option<Arc<ApiKey>>,
+}
+
+impl Identity {
+    /// Tenant owning the API key, if the request used one.
+    pub fn tenant(&self) -> Option<&str> {
+        self.api_key.as_ref().map(|k| k.owner.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("missing credentials")]
+    Missing,
+    #[error("invalid bearer token")]
+    InvalidToken,
+    #[error("API key disabled")]
+    KeyDisabled,
+    #[error("API key expired")]
+    KeyExpired,
+    #[error("missing Cloudflare Access assertion")]
+    MissingAccessAssertion,
+    #[error("invalid Cloudflare Access assertion: {0}")]
+    CloudflareAccess(JwtError),
+    #[error("{0}")]
+    Jwt(#[from] JwtError),
+}
+
+impl AuthError {
+    /// A fixed description for the `WWW-Authenticate` challenge; the
+    /// `Display` text may carry claims from an unverified token.
+    pub fn description(&self) -> &'static str {
+        match self {
+            AuthError::Missing => "missing credentials",
+            AuthError::InvalidToken => "invalid bearer token",
+            AuthError::KeyDisabled => "API key disabled",
+            AuthError::KeyExpired => "API key expired",
+            AuthError::MissingAccessAssertion => "missing Cloudflare Access assertion",
+            AuthError::CloudflareAccess(_) => "invalid Cloudflare Access assertion",
+            AuthError::Jwt(e) => e.description(),
+        }
+    }
+}
+
+/// Resolves request credentials to an [`Identity`].
+pub struct Authenticator {
+    bearer: Option<String>,
+    keys: KeyStore,
+    oidc: OidcVerifier,
+    cf_access: Option<CfAccessVerifier>,
+}
+
+impl Authenticator {
+    pub fn new(client: ReqwestClient, bearer: Option<String>, auth: &Auth, keys: KeyStore) -> Self {
+        Self {
+            bearer,
+            keys,
+            oidc: OidcVerifier::new(client.clone(), &auth.oidc_providers),
+            cf_access: auth
+                .cloudflare_access
+                .as_ref()
+                .map(|cfg| CfAccessVerifier::new(client, cfg)),
+        }
+    }
+
+    /// Accept the shared bearer token, a key-store API key (as a bearer
+    /// token or `X-API-Key`) or an OIDC-issued JWT. With none configured,
+    /// requests pass as anonymous. When Cloudflare Access is configured its
+    /// assertion is required on top.
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, AuthError> {
+        let header = headers.get(AUTHORIZATION).and_then(|v| v.to_str().ok());
+        let token = header
+            .map(|h| h.strip_prefix("Bearer ").unwrap_or(h))
+            .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()));
+
+        let mut identity = if header.is_some_and(|h| self.matches_static(h)) {
+            Identity {
+                method: AuthMethod::StaticToken,
+                ..Identity::default()
+            }
+        } else if let Some(found) = token.and_then(|t| self.keys.lookup(t)) {
+            let key = found.map_err(|r| match r {
+                KeyRejection::Disabled => AuthError::KeyDisabled,
+                KeyRejection::Expired => AuthError::KeyExpired,
+            })?;
+            Identity {
+                method: AuthMethod::ApiKey,
+                subject: Some(key.id.clone()),
+                api_key: Some(key),
+                ..Identity::default()
+            }
+        } else if let Some(t) = token.filter(|_| !self.oidc.is_empty()) {
+            if !looks_like_jwt(t) {
+                return Err(AuthError::InvalidToken);
+            }
+            let verified = self.oidc.verify(t).await?;
+            Identity {
+                method: AuthMethod::Oidc,
+                issuer: verified.claim("iss"),
+                subject: verified.claim("sub"),
+                email: verified.claim("email"),
+                provider: Some(verified.provider),
+                ..Identity::default()
+            }
+        } else if self.bearer.is_none() && self.keys.is_empty() && self.oidc.is_empty() {
+            Identity::default()
+        } else if token.is_some() {
+            return Err(AuthError::InvalidToken);
+        } else {
+            return Err(AuthError::Missing);
+        };
+
+        if let Some(cf) = &self.cf_access {
+            let assertion = headers
+                .get("cf-access-jwt-assertion")
+                .and_then(|v| v.to_str().ok())
+                .ok_or(AuthError::MissingAccessAssertion)?;
+            let access = cf
+                .verify(assertion)
+                .await
+                .map_err(AuthError::CloudflareAccess)?;
+            if identity.method == AuthMethod::Anonymous {
+                identity.method = AuthMethod::CloudflareAccess;
+                identity.subject = access.subject;
+                identity.email = access.email.clone();
+            }
+            identity.access_email = access.email;
+        }
+        Ok(identity)
+    }
+
+    fn matches_static(&self, header: &str) -> bool {
+        self.bearer
+            .as_ref()
+            .map(|token| header == format!("Bearer {}", token) || header == token)
+            .unwrap_or(false)
+    }
+}
@@ -1,16 +1,103 @@
 // src/backend_registry.rs
 
+use crate::config::{Backend, OutlierDetection};
 use parking_lot::RwLock;
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum OutlierError {
+    #[error("invalid ejection time '{0}': {1}")]
+    Duration(String, humantime::DurationError),
+}
+
+/// When live traffic ejects a backend, and for how long.
+#[derive(Debug, Clone, Copy)]
+pub struct OutlierPolicy {
+    consecutive_errors: u32,
+    base_ejection: Duration,
+    max_ejection: Duration,
+    max_ejected_percent: u32,
+}
+
+impl OutlierPolicy {
+    pub fn new(cfg: &OutlierDetection) -> Result<Self, OutlierError> {
+        let duration = |s: &String| {
+            humantime::parse_duration(s).map_err(|e| OutlierError::Duration(s.clone(), e))
+        };
+        Ok(Self {
+            consecutive_errors: cfg.consecutive_errors.max(1),
+            base_ejection: duration(&cfg.base_ejection)?,
+            max_ejection: duration(&cfg.max_ejection)?,
+            max_ejected_percent: cfg.max_ejected_percent,
+        })
+    }
+
+    /// Doubles with every ejection since the backend was last restored.
+    fn ejection(&self, ejections: u32) -> Duration {
+        let factor = 2u32.saturating_pow(ejections.saturating_sub(1));
+        self.base_ejection
+            .saturating_mul(factor)
+            .min(self.max_ejection)
+    }
+}
+
+/// Circuit breaker state of an entry.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Circuit {
+    #[default]
+    Closed,
+    /// Ejected; not picked until the ejection ends
+    Open,
+    /// Ejection over; the next request is a trial
+    HalfOpen,
+}
+
+/// Passive outlier detection state of an entry.
+#[derive(Debug, Default, Clone)]
+struct Outlier {
+    errors: u32,
+    ejections: u32,
+    ejected_until: Option<Instant>,
+    /// A half-open trial request is in flight
+    trial: bool,
+}
+
+impl Outlier {
+    fn circuit(&self, now: Instant) -> Circuit {
+        match self.ejected_until {
+            None => Circuit::Closed,
+            Some(t) if t > now => Circuit::Open,
+            Some(_) => Circuit::HalfOpen,
+        }
+    }
+}
 
 /// Service entry for discovery
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ServiceEntry {
     #[allow(dead_code)]
     pub name: String,
     pub url: String,
+    /// Cleared by failing health checks; `pick_one` skips unhealthy entries
+    #[serde(default = "healthy")]
+    pub healthy: bool,
+    /// Why the last health check failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Outlier detection state, as of the last `snapshot`
+    #[serde(default)]
+    pub circuit: Circuit,
+    #[serde(skip)]
+    outlier: Outlier,
+}
+
+fn healthy() -> bool {
+    true
 }
 
 /// Thread-safe registry mapping service names → backend entries
@@ -18,6 +105,7 @@ pub struct ServiceEntry {
 pub struct BackendRegistry {
     services: Arc<RwLock<HashMap<String, Vec<ServiceEntry>>>>,
     indices: Arc<RwLock<HashMap<String, usize>>>,
+    outliers: Arc<RwLock<Option<OutlierPolicy>>>,
 }
 
 impl BackendRegistry {
@@ -31,6 +119,10 @@ impl BackendRegistry {
         let entry = ServiceEntry {
             name: name.to_string(),
             url: url.to_string(),
+            healthy: true,
+            last_error: None,
+            circuit: Circuit::Closed,
+            outlier: Outlier::default(),
         };
         map.entry(name.to_string()).or_default().push(entry);
     }
@@ -43,19 +135,149 @@ impl BackendRegistry {
         }
     }
 
-    /// Pick one backend URL (round-robin) under `name`
+    /// Replace the registered backends with `backends`, as after a config
+    /// reload. Entries that stay keep their health and outlier state.
+    pub fn sync(&self, backends: &[Backend]) {
+        let mut services = self.services.write();
+        let mut next: HashMap<String, Vec<ServiceEntry>> = HashMap::new();
+        for be in backends {
+            let kept = services.get_mut(&be.name).and_then(|entries| {
+                let i = entries.iter().position(|e| e.url == be.address)?;
+                Some(entries.remove(i))
+            });
+            next.entry(be.name.clone())
+                .or_default()
+                .push(kept.unwrap_or(ServiceEntry {
+                    name: be.name.clone(),
+                    url: be.address.clone(),
+                    healthy: true,
+                    last_error: None,
+                    circuit: Circuit::Closed,
+                    outlier: Outlier::default(),
+                }));
+        }
+        *services = next;
+        self.indices
+            .write()
+            .retain(|name, _| services.contains_key(name));
+    }
+
+    /// Enable (or turn off) passive outlier detection.
+    pub fn set_outlier_policy(&self, policy: Option<OutlierPolicy>) {
+        *self.outliers.write() = policy;
+    }
+
+    /// Pick one healthy backend URL (round-robin) under `name`
     pub fn pick_one(&self, name: &str) -> Option<String> {
-        let services = self.services.write();
-        let backends = services.get(name)?;
+        self.pick_excluding(name, &[])
+    }
+
+    /// Like `pick_one`, but skipping the URLs in `exclude` (e.g. backends a
+    /// request already failed on).
+    pub fn pick_excluding(&self, name: &str, exclude: &[String]) -> Option<String> {
+        let mut services = self.services.write();
+        let backends = services.get_mut(name)?;
         if backends.is_empty() {
             return None;
         }
-        // bump & wrap the index
+        // bump & wrap the index, stepping over unhealthy and ejected entries
+        let now = Instant::now();
         let mut idx_map = self.indices.write();
         let ctr = idx_map.entry(name.to_string()).or_insert(0);
-        let url = backends[*ctr % backends.len()].url.clone();
-        *ctr = (*ctr + 1) % backends.len();
-        Some(url)
+        let n = backends.len();
+        let i = (0..n).map(|k| (*ctr + k) % n).find(|&i| {
+            let e = &backends[i];
+            e.healthy && e.outlier.circuit(now) != Circuit::Open && !exclude.contains(&e.url)
+        })?;
+        *ctr = (i + 1) % n;
+        let entry = &mut backends[i];
+        if entry.outlier.circuit(now) == Circuit::HalfOpen {
+            // One trial at a time: closed to others until it reports back,
+            // or for another base ejection if it never does.
+            let base = self
+                .outliers
+                .read()
+                .map_or(Duration::ZERO, |p| p.base_ejection);
+            entry.outlier.trial = true;
+            entry.outlier.ejected_until = Some(now + base);
+        }
+        Some(entry.url.clone())
+    }
+
+    /// Record the outcome of a live request to the backend at `url`: a 5xx
+    /// response, connect failure or timeout is `ok == false`. Applies to
+    /// every service the URL is registered under.
+    pub fn report(&self, url: &str, ok: bool) {
+        let Some(policy) = *self.outliers.read() else {
+            return;
+        };
+        let now = Instant::now();
+        let mut services = self.services.write();
+        for entries in services.values_mut() {
+            let n = entries.len() as u32;
+            let ejected = entries
+                .iter()
+                .filter(|e| e.outlier.ejected_until.is_some())
+                .count() as u32;
+            for e in entries.iter_mut().filter(|e| e.url == url) {
+                let o = &mut e.outlier;
+                if ok {
+                    o.errors = 0;
+                    if o.trial {
+                        *o = Outlier::default();
+                    }
+                    continue;
+                }
+                o.errors += 1;
+                let may_eject = (ejected + 1) * 100 <= policy.max_ejected_percent * n;
+                let eject = o.trial
+                    || (o.ejected_until.is_none()
+                        && o.errors >= policy.consecutive_errors
+                        && may_eject);
+                if eject {
+                    o.ejections += 1;
+                    o.ejected_until = Some(now + policy.ejection(o.ejections));
+                    o.errors = 0;
+                    o.trial = false;
+                }
+            }
+        }
+    }
+
+    /// The health check state of the backend at `url` under `name`.
+    pub fn is_healthy(&self, name: &str, url: &str) -> Option<bool> {
+        self.services
+            .read()
+            .get(name)?
+            .iter()
+            .find(|e| e.url == url)
+            .map(|e| e.healthy)
+    }
+
+    /// Record a health check outcome for the backend at `url` under `name`.
+    pub fn set_health(&self, name: &str, url: &str, healthy: bool, error: Option<String>) {
+        if let Some(vec) = self.services.write().get_mut(name) {
+            for e in vec.iter_mut().filter(|e| e.url == url) {
+                e.healthy = healthy;
+                e.last_error = error.clone();
+            }
+        }
+    }
+
+    /// Every service and its entries, ordered by name.
+    pub fn snapshot(&self) -> BTreeMap<String, Vec<ServiceEntry>> {
+        let now = Instant::now();
+        self.services
+            .read()
+            .iter()
+            .map(|(k, v)| {
+                let mut entries = v.clone();
+                for e in &mut entries {
+                    e.circuit = e.outlier.circuit(now);
+                }
+                (k.clone(), entries)
+            })
+            .collect()
     }
 
     /// List all backend URLs under `name`
@@ -73,3 +295,105 @@ impl BackendRegistry {
         self.services.read().get(name).cloned().unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(urls: &[&str]) -> BackendRegistry {
+        let r = BackendRegistry::new();
+        for url in urls {
+            r.register("pool", url);
+        }
+        r.set_outlier_policy(Some(
+            OutlierPolicy::new(&OutlierDetection {
+                consecutive_errors: 2,
+                base_ejection: "40ms".into(),
+                max_ejection: "1s".into(),
+                max_ejected_percent: 50,
+            })
+            .unwrap(),
+        ));
+        r
+    }
+
+    fn circuit(r: &BackendRegistry, i: usize) -> Circuit {
+        r.snapshot()["pool"][i].circuit
+    }
+
+    #[test]
+    fn test_ejects_and_restores_through_half_open() {
+        let r = registry(&["a", "b", "c"]);
+        r.report("a", false);
+        r.report("a", true);
+        r.report("a", false);
+        assert_eq!(circuit(&r, 0), Circuit::Closed);
+        r.report("a", false);
+        assert_eq!(circuit(&r, 0), Circuit::Open);
+        for _ in 0..4 {
+            assert_ne!(r.pick_one("pool").as_deref(), Some("a"));
+        }
+        // At most half of the pool: "b" keeps serving.
+        r.report("b", false);
+        r.report("b", false);
+        assert_eq!(circuit(&r, 1), Circuit::Closed);
+
+        // After the ejection one trial goes through; its failure doubles
+        // the next ejection.
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(circuit(&r, 0), Circuit::HalfOpen);
+        let trial = (0..3).find_map(|_| r.pick_one("pool").filter(|u| u == "a"));
+        assert_eq!(trial.as_deref(), Some("a"));
+        r.report("a", false);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(circuit(&r, 0), Circuit::Open);
+        std::thread::sleep(Duration::from_millis(40));
+        assert_eq!(circuit(&r, 0), Circuit::HalfOpen);
+        let trial = (0..3).find_map(|_| r.pick_one("pool").filter(|u| u == "a"));
+        assert!(trial.is_some());
+        r.report("a", true);
+        assert_eq!(circuit(&r, 0), Circuit::Closed);
+    }
+
+    #[test]
+    fn test_ejection_cap_holds_for_small_pools() {
+        // Ejecting the only backend would be 100% of the pool.
+        let r = registry(&["only"]);
+        r.report("only", false);
+        r.report("only", false);
+        assert_eq!(circuit(&r, 0), Circuit::Closed);
+        assert_eq!(r.pick_one("pool").as_deref(), Some("only"));
+
+        let r = registry(&["a", "b"]);
+        r.set_outlier_policy(Some(
+            OutlierPolicy::new(&OutlierDetection {
+                consecutive_errors: 2,
+                base_ejection: "40ms".into(),
+                max_ejection: "1s".into(),
+                max_ejected_percent: 0,
+            })
+            .unwrap(),
+        ));
+        r.report("a", false);
+        r.report("a", false);
+        assert_eq!(circuit(&r, 0), Circuit::Closed);
+    }
+
+    #[test]
+    fn test_sync_keeps_state_of_remaining_entries() {
+        let r = registry(&["a", "b"]);
+        r.set_health("pool", "b", false, Some("timed out".into()));
+        let backends: Vec<Backend> = serde_yaml::from_str(
+            "[ { name: pool, protocol: http, address: b },
+               { name: pool, protocol: http, address: c },
+               { name: other, protocol: http, address: d } ]",
+        )
+        .unwrap();
+        r.sync(&backends);
+        assert_eq!(r.list("pool"), ["b", "c"]);
+        assert_eq!(r.is_healthy("pool", "b"), Some(false));
+        assert_eq!(r.is_healthy("pool", "c"), Some(true));
+        assert_eq!(r.pick_one("pool").as_deref(), Some("c"));
+        assert_eq!(r.pick_one("other").as_deref(), Some("d"));
+    }
+}
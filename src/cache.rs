@@ -0,0 +1,427 @@
+// src/cache.rs
+
+use crate::config::{CacheBackend, CacheConfig};
+use bytes::Bytes;
+use hyper::HeaderMap;
+use log::warn;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("invalid cache ttl '{0}': {1}")]
+    Ttl(String, humantime::DurationError),
+    #[error("the disk cache backend needs `dir`")]
+    MissingDir,
+    #[error("failed to create cache dir '{path}': {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+/// What the client asked for in `Cache-Control`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheDirective {
+    Default,
+    /// Skip the lookup but store the fresh response
+    NoCache,
+    /// Neither look up nor store
+    NoStore,
+}
+
+impl CacheDirective {
+    pub fn of(headers: &HeaderMap) -> Self {
+        let mut directive = CacheDirective::Default;
+        for v in headers.get_all(hyper::header::CACHE_CONTROL) {
+            for d in v.to_str().unwrap_or("").split(',') {
+                match d.trim().to_ascii_lowercase().as_str() {
+                    "no-store" => return CacheDirective::NoStore,
+                    "no-cache" => directive = CacheDirective::NoCache,
+                    _ => {}
+                }
+            }
+        }
+        directive
+    }
+}
+
+/// Embeddings are always deterministic; generations only when sampling is
+/// turned off with `options.temperature: 0`.
+pub fn cacheable(path: &str, doc: &Value) -> bool {
+    match path {
+        "/api/embed" | "/api/embeddings" => true,
+        "/api/generate" | "/api/chat" => {
+            doc.pointer("/options/temperature").and_then(Value::as_f64) == Some(0.0)
+        }
+        _ => false,
+    }
+}
+
+/// Cache key for a request. `scope` keeps tenants apart. The body is
+/// re-serialized so key order and whitespace don't matter, and `keep_alive`
+/// (which doesn't affect the output) is ignored.
+pub fn cache_key(scope: &str, path: &str, doc: &Value) -> String {
+    let mut doc = doc.clone();
+    if let Some(obj) = doc.as_object_mut() {
+        obj.remove("keep_alive");
+    }
+    let canonical = serde_json::to_string(&doc).unwrap_or_default();
+    crate::key_store::sha256_hex(&format!("{}\n{}\n{}", scope, path, canonical))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub content_type: Option<String>,
+    #[serde(skip)]
+    pub body: Bytes,
+}
+
+/// Stored next to the body by the disk backend.
+#[derive(Serialize, Deserialize)]
+struct DiskHeader {
+    expires: u64,
+    #[serde(flatten)]
+    response: CachedResponse,
+}
+
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, u64, CachedResponse)>,
+    order: BTreeMap<u64, String>,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str, now: u64) -> Option<CachedResponse> {
+        let (expires, last, _) = self.entries.get(key)?;
+        let (expires, last) = (*expires, *last);
+        self.order.remove(&last);
+        if expires <= now {
+            self.entries.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.to_string());
+        let entry = self.entries.get_mut(key)?;
+        entry.1 = self.tick;
+        Some(entry.2.clone())
+    }
+
+    fn put(&mut self, key: String, expires: u64, resp: CachedResponse) {
+        if let Some((_, last, _)) = self.entries.remove(&key) {
+            self.order.remove(&last);
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (expires, self.tick, resp));
+    }
+}
+
+/// How often the disk backend looks for expired entries.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// Temp files older than this are left over from an interrupted write.
+const TMP_GRACE: Duration = Duration::from_secs(60);
+
+struct Disk {
+    dir: PathBuf,
+    max_entries: usize,
+    /// Entries found by the last sweep plus those written since, and when
+    /// it ran
+    sweep: Mutex<(usize, Instant)>,
+}
+
+impl Disk {
+    /// Count a new entry; `true` if it is time to sweep.
+    fn wrote(&self) -> bool {
+        let mut sweep = self.sweep.lock();
+        sweep.0 += 1;
+        let due = sweep.0 > self.max_entries || sweep.1.elapsed() >= SWEEP_INTERVAL;
+        if due {
+            // Claimed by this writer; others carry on until it is done.
+            *sweep = (0, Instant::now());
+        }
+        due
+    }
+}
+
+/// Whether `name` is one the cache writes: `Some(true)` for a temp file,
+/// `Some(false)` for an entry, `None` for anything else in `dir`.
+fn cache_file(name: &str) -> Option<bool> {
+    let (key, tmp) = match name.strip_suffix(".tmp") {
+        Some(key) => (key, true),
+        None => (name, false),
+    };
+    let hex = key.len() == 64
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    hex.then_some(tmp)
+}
+
+/// Delete expired entries and stray temp files from `dir`, then the oldest
+/// entries beyond `max_entries`. Files the cache did not write are left
+/// alone. Returns the number of entries left.
+fn sweep(dir: &Path, ttl: Duration, max_entries: usize) -> usize {
+    let Ok(read) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let now = SystemTime::now();
+    let mut entries = Vec::new();
+    for entry in read.flatten() {
+        let path = entry.path();
+        let Some(tmp) = entry.file_name().to_str().and_then(cache_file) else {
+            continue;
+        };
+        let Ok(modified) = entry.metadata().and_then(|m| m.modified()) else {
+            continue;
+        };
+        let age = now.duration_since(modified).unwrap_or_default();
+        if (tmp && age >= TMP_GRACE) || (!tmp && age >= ttl) {
+            let _ = std::fs::remove_file(&path);
+        } else if !tmp {
+            entries.push((modified, path));
+        }
+    }
+    entries.sort();
+    let excess = entries.len().saturating_sub(max_entries);
+    for (_, path) in entries.drain(..excess) {
+        let _ = std::fs::remove_file(&path);
+    }
+    entries.len()
+}
+
+enum Store {
+    Memory(Mutex<Lru>),
+    Disk(Disk),
+}
+
+/// Responses to deterministic requests, kept in memory (LRU) or on disk.
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entry_bytes: usize,
+    store: Store,
+}
+
+impl ResponseCache {
+    pub fn new(cfg: &CacheConfig) -> Result<Self, CacheError> {
+        let ttl =
+            humantime::parse_duration(&cfg.ttl).map_err(|e| CacheError::Ttl(cfg.ttl.clone(), e))?;
+        let store = match cfg.backend {
+            CacheBackend::Memory => Store::Memory(Mutex::new(Lru {
+                capacity: cfg.max_entries.max(1),
+                tick: 0,
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+            })),
+            CacheBackend::Disk => {
+                let dir = cfg.dir.as_ref().ok_or(CacheError::MissingDir)?;
+                std::fs::create_dir_all(dir).map_err(|source| CacheError::Io {
+                    path: dir.clone(),
+                    source,
+                })?;
+                let max_entries = cfg.max_entries.max(1);
+                let found = sweep(Path::new(dir), ttl, max_entries);
+                Store::Disk(Disk {
+                    dir: PathBuf::from(dir),
+                    max_entries,
+                    sweep: Mutex::new((found, Instant::now())),
+                })
+            }
+        };
+        Ok(Self {
+            ttl,
+            max_entry_bytes: cfg.max_entry_bytes,
+            store,
+        })
+    }
+
+    pub fn max_entry_bytes(&self) -> usize {
+        self.max_entry_bytes
+    }
+
+    pub async fn get(&self, key: &str) -> Option<CachedResponse> {
+        let now = unix_now();
+        match &self.store {
+            Store::Memory(lru) => lru.lock().get(key, now),
+            Store::Disk(disk) => {
+                let path = disk.dir.join(key);
+                let raw = tokio::fs::read(&path).await.ok()?;
+                let split = raw.iter().position(|&b| b == b'\n')?;
+                let header: DiskHeader = serde_json::from_slice(&raw[..split]).ok()?;
+                if header.expires <= now {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return None;
+                }
+                Some(CachedResponse {
+                    body: Bytes::copy_from_slice(&raw[split + 1..]),
+                    ..header.response
+                })
+            }
+        }
+    }
+
+    pub async fn put(&self, key: &str, resp: CachedResponse) {
+        if resp.body.len() > self.max_entry_bytes {
+            return;
+        }
+        let expires = unix_now() + self.ttl.as_secs();
+        match &self.store {
+            Store::Memory(lru) => lru.lock().put(key.to_string(), expires, resp),
+            Store::Disk(disk) => {
+                let dir = &disk.dir;
+                let body = resp.body.clone();
+                let Ok(mut raw) = serde_json::to_vec(&DiskHeader {
+                    expires,
+                    response: resp,
+                }) else {
+                    return;
+                };
+                raw.push(b'\n');
+                raw.extend_from_slice(&body);
+                // Write then rename so readers never see a partial entry.
+                let tmp = dir.join(format!("{}.tmp", key));
+                let result = async {
+                    tokio::fs::write(&tmp, &raw).await?;
+                    tokio::fs::rename(&tmp, dir.join(key)).await
+                }
+                .await;
+                if let Err(e) = result {
+                    warn!("failed to write cache entry {}: {}", key, e);
+                    return;
+                }
+                if disk.wrote() {
+                    let (dir, ttl, max) = (dir.clone(), self.ttl, disk.max_entries);
+                    if let Ok(left) =
+                        tokio::task::spawn_blocking(move || sweep(&dir, ttl, max)).await
+                    {
+                        disk.sweep.lock().0 += left;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(backend: CacheBackend, dir: Option<String>) -> CacheConfig {
+        CacheConfig {
+            backend,
+            ttl: "1h".into(),
+            max_entries: 2,
+            max_entry_bytes: 16,
+            dir,
+        }
+    }
+
+    fn resp(body: &'static str) -> CachedResponse {
+        CachedResponse {
+            content_type: Some("application/json".into()),
+            body: Bytes::from(body),
+        }
+    }
+
+    #[test]
+    fn test_keys_and_cacheability() {
+        let a = json!({"model": "m", "input": "x", "keep_alive": "5m"});
+        let b: Value = serde_json::from_str(r#"{ "input":"x",  "model":"m" }"#).unwrap();
+        assert_eq!(
+            cache_key("t1", "/api/embed", &a),
+            cache_key("t1", "/api/embed", &b)
+        );
+        assert_ne!(
+            cache_key("t1", "/api/embed", &a),
+            cache_key("t2", "/api/embed", &a)
+        );
+        assert!(cacheable("/api/embed", &a));
+        assert!(!cacheable("/api/chat", &json!({"messages": []})));
+        assert!(cacheable(
+            "/api/chat",
+            &json!({"options": {"temperature": 0}})
+        ));
+
+        let mut h = HeaderMap::new();
+        h.insert("cache-control", "max-age=0, no-cache".parse().unwrap());
+        assert_eq!(CacheDirective::of(&h), CacheDirective::NoCache);
+    }
+
+    #[tokio::test]
+    async fn test_memory_lru_and_disk() {
+        let cache = ResponseCache::new(&config(CacheBackend::Memory, None)).unwrap();
+        cache.put("a", resp("1")).await;
+        cache.put("b", resp("2")).await;
+        assert!(cache.get("a").await.is_some());
+        // "b" is now least recently used and makes room for "c".
+        cache.put("c", resp("3")).await;
+        assert!(cache.get("b").await.is_none());
+        assert_eq!(&cache.get("a").await.unwrap().body[..], b"1");
+        cache.put("big", resp("this is more than sixteen")).await;
+        assert!(cache.get("big").await.is_none());
+
+        let dir = std::env::temp_dir().join(format!("gamb-cache-{}", std::process::id()));
+        let cache =
+            ResponseCache::new(&config(CacheBackend::Disk, Some(dir.display().to_string())))
+                .unwrap();
+        cache.put("k", resp("{\"x\":1}\n")).await;
+        let hit = cache.get("k").await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(&hit.body[..], b"{\"x\":1}\n");
+        assert_eq!(hit.content_type.as_deref(), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn test_disk_sweeps_and_evicts() {
+        let dir = std::env::temp_dir().join(format!("gamb-sweep-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key = |n: u8| format!("{:064x}", n);
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        let stale = format!("{}.tmp", key(1));
+        for name in [stale.as_str(), &key(2), "notes.txt"] {
+            let file = std::fs::File::create(dir.join(name)).unwrap();
+            file.set_modified(hour_ago).unwrap();
+        }
+        let mut cfg = config(CacheBackend::Disk, Some(dir.display().to_string()));
+        cfg.ttl = "30m".into();
+        let cache = ResponseCache::new(&cfg).unwrap();
+        assert!(!dir.join(&stale).exists());
+        assert!(!dir.join(key(2)).exists());
+
+        // The third entry is one over `max_entries`: the oldest goes.
+        for n in 3..6 {
+            cache.put(&key(n), resp("1")).await;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let left = std::fs::read_dir(&dir).unwrap().count();
+        let evicted = cache.get(&key(3)).await.is_none();
+        // A file the cache did not write survives, however old.
+        let foreign = dir.join("notes.txt").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, 3);
+        assert!(evicted);
+        assert!(foreign);
+    }
+}
@@ -0,0 +1,123 @@
+// src/cf_access.rs
+
+use crate::{
+    config::CloudflareAccess,
+    jwks::{parse_algorithms, JwksCache, JwksSource, JwtError},
+};
+use jsonwebtoken::{Algorithm, Validation};
+use reqwest::Client as ReqwestClient;
+
+/// Identity Cloudflare Access vouched for in a verified assertion.
+#[derive(Debug, Clone)]
+pub struct AccessIdentity {
+    pub subject: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Verifies the `cf-access-jwt-assertion` header against the team's
+/// certs endpoint (`https://<team>/cdn-cgi/access/certs`).
+pub struct CfAccessVerifier {
+    algorithms: Vec<Algorithm>,
+    validation: Validation,
+    jwks: JwksCache,
+}
+
+impl CfAccessVerifier {
+    pub fn new(client: ReqwestClient, cfg: &CloudflareAccess) -> Self {
+        let issuer = team_url(&cfg.team_domain);
+        let certs_url = cfg
+            .certs_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/cdn-cgi/access/certs", issuer));
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_issuer(&[issuer]);
+        validation.set_audience(&[&cfg.aud]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        validation.validate_nbf = true;
+        validation.leeway = cfg.clock_skew_secs;
+        Self {
+            algorithms: parse_algorithms(&["RS256".to_string()]),
+            validation,
+            jwks: JwksCache::new(client, JwksSource::Direct { url: certs_url }),
+        }
+    }
+
+    pub async fn verify(&self, assertion: &str) -> Result<AccessIdentity, JwtError> {
+        let claims = self
+            .jwks
+            .verify(assertion, &self.algorithms, &self.validation)
+            .await?;
+        let get = |k: &str| claims.get(k).and_then(|v| v.as_str()).map(str::to_string);
+        Ok(AccessIdentity {
+            subject: get("sub"),
+            email: get("email"),
+        })
+    }
+}
+
+/// `myteam` and `myteam.cloudflareaccess.com` both name the same team.
+fn team_url(team_domain: &str) -> String {
+    let domain = team_domain
+        .trim_start_matches("https://")
+        .trim_end_matches('/');
+    if domain.contains('.') {
+        format!("https://{}", domain)
+    } else {
+        format!("https://{}.cloudflareaccess.com", domain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oidc::tests::{jwks_stub, sign, JWKS_1, KEY_1, KEY_2};
+    use jsonwebtoken::get_current_timestamp;
+    use serde_json::json;
+
+    fn access_cfg(certs_base: &str) -> CloudflareAccess {
+        CloudflareAccess {
+            team_domain: "acme".into(),
+            aud: "aud-tag-123".into(),
+            certs_url: Some(format!("{}/cdn-cgi/access/certs", certs_base)),
+            clock_skew_secs: 60,
+        }
+    }
+
+    fn assertion(key: &str, iss: &str, aud: &str) -> String {
+        let exp = get_current_timestamp() + 300;
+        let claims = json!({
+            "iss": iss, "aud": [aud], "sub": "u-1", "email": "dev@acme.io", "exp": exp
+        });
+        sign(key, "test-key-1", claims)
+    }
+
+    #[tokio::test]
+    async fn test_verifies_access_assertion() {
+        let (base, _) = jwks_stub(JWKS_1).await;
+        let v = CfAccessVerifier::new(ReqwestClient::new(), &access_cfg(&base));
+        let team = "https://acme.cloudflareaccess.com";
+
+        let id = v
+            .verify(&assertion(KEY_1, team, "aud-tag-123"))
+            .await
+            .unwrap();
+        assert_eq!(id.email.as_deref(), Some("dev@acme.io"));
+
+        let wrong_aud = assertion(KEY_1, team, "other-app");
+        assert!(matches!(
+            v.verify(&wrong_aud).await,
+            Err(JwtError::InvalidAudience)
+        ));
+        let wrong_team = assertion(KEY_1, "https://evil.cloudflareaccess.com", "aud-tag-123");
+        assert!(matches!(
+            v.verify(&wrong_team).await,
+            Err(JwtError::InvalidIssuer)
+        ));
+        let forged = assertion(KEY_2, team, "aud-tag-123");
+        assert!(matches!(
+            v.verify(&forged).await,
+            Err(JwtError::InvalidSignature)
+        ));
+        assert!(v.verify("not-a-jwt").await.is_err());
+    }
+}
@@ -1,5 +1,10 @@
+use crate::validate::{self, Issue};
 use serde::Deserialize;
-use std::{collections::HashSet, fs::File, io::BufReader, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{AddrParseError, IpAddr, SocketAddr},
+    path::Path,
+};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -10,11 +15,12 @@ pub enum ConfigError {
         source: std::io::Error,
     },
 
-    #[error("failed to parse YAML config: {0}")]
-    Yaml(#[from] serde_yaml::Error),
+    #[error("invalid config: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Invalid(Vec<Issue>),
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub http_port: u16,
     pub https_port: Option<u16>,
@@ -28,6 +34,15 @@ pub struct Config {
     pub auth: Auth,
     pub tls: Tls,
     pub backends: Vec<Backend>,
+    /// Eject backends that keep failing live requests
+    #[serde(default)]
+    pub outlier_detection: Option<OutlierDetection>,
+    /// Retry failed upstream requests on another backend
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+    /// Reloading this file without a restart
+    #[serde(default)]
+    pub reload: ReloadConfig,
     pub consul_url: String,
     pub tls_mode: String,
     #[allow(dead_code)]
@@ -35,43 +50,485 @@ pub struct Config {
     #[allow(dead_code)]
     pub tls_email: String,
     pub bearer_token: Option<String>,
+    /// Bearer token for the `/admin/*` endpoints; they are off without one
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Tenant API keys: a YAML/JSON file or a directory of them
+    #[serde(default)]
+    pub key_store: Option<String>,
     pub rate_limit_per_sec: u32,
     pub rate_limit_burst: u32,
+    /// Per route class overrides (`generate`, `embed`, `tags`, `other`)
+    #[serde(default)]
+    pub rate_limit_classes: HashMap<String, ClassLimit>,
     #[serde(default)]
     pub proxy: ProxyConfig,
+    /// Token budgets for LLM endpoints
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+    /// JSONL audit log of HTTP requests
+    #[serde(default)]
+    pub audit: Option<AuditConfig>,
+    /// Sensitive data detection in prompts
+    #[serde(default)]
+    pub dlp: DlpConfig,
+    /// Named rules over prompt content, evaluated in order
+    #[serde(default)]
+    pub content_rules: Vec<ContentRule>,
+    /// Cache for embeddings and deterministic generations
+    #[serde(default)]
+    pub cache: Option<CacheConfig>,
+    /// Admission control for generation and embedding requests
+    #[serde(default)]
+    pub concurrency: Option<ConcurrencyConfig>,
+    /// OpenAI-compatible endpoints
+    #[serde(default)]
+    pub openai: OpenAiConfig,
+    /// Model aliases
+    #[serde(default)]
+    pub models: ModelsConfig,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ModelsConfig {
+    /// Client-facing name -> upstream model tag
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+    /// List only the aliases in `/api/tags` and `/v1/models`
+    #[serde(default)]
+    pub hide_unaliased: bool,
+    /// Model-aware routing to backend services; the first match wins
+    #[serde(default)]
+    pub routes: Vec<ModelRoute>,
+}
+
+/// Send requests for matching models to a backend service.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ModelRoute {
+    /// Model names or globs (`*`, `?`), e.g. `llama3*`
+    pub models: Vec<String>,
+    /// `Backend.name` of the pool that serves them
+    pub service: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct OpenAiConfig {
+    /// Serve `/v1/*` by translating to the native Ollama API instead of
+    /// passing the requests through
+    #[serde(default)]
+    pub translate: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub backend: CacheBackend,
+    /// How long an entry stays fresh, e.g. `1h`
+    #[serde(default = "default_cache_ttl")]
+    pub ttl: String,
+    /// Entries kept before evicting: the least recently used from memory,
+    /// the oldest from disk
+    #[serde(default = "default_cache_entries")]
+    pub max_entries: usize,
+    /// Larger responses are not cached
+    #[serde(default = "default_cache_entry_bytes")]
+    pub max_entry_bytes: usize,
+    /// Directory for the disk backend
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    /// In-flight limits applied to each matching model; the first match wins
+    #[serde(default)]
+    pub models: Vec<ModelLimit>,
+    /// In-flight limit for every upstream URL
+    #[serde(default)]
+    pub per_upstream: Option<usize>,
+    /// Upstream URL -> in-flight limit, overriding `per_upstream`
+    #[serde(default)]
+    pub upstreams: HashMap<String, usize>,
+    /// Requests waiting for a slot before new ones are refused
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize,
+    /// How long a request may wait for a slot, e.g. `30s`
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout: String,
+    /// Priority classes, highest first
+    #[serde(default)]
+    pub classes: Vec<PriorityClass>,
+    /// Class for callers without one; the first class if unset
+    #[serde(default)]
+    pub default_class: Option<String>,
+    /// Tenant -> class, for keys without their own `priority`
+    #[serde(default)]
+    pub tenants: HashMap<String, String>,
+    /// Request header naming a class; it can only lower the caller's class
+    #[serde(default)]
+    pub priority_header: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PriorityClass {
+    pub name: String,
+    /// Slots per limit this class may use even while higher classes wait
+    #[serde(default)]
+    pub reserved: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ModelLimit {
+    /// Model names or globs (`*`, `?`)
+    pub models: Vec<String>,
+    pub max_in_flight: usize,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    Memory,
+    Disk,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ContentRule {
+    pub name: String,
+    pub action: RuleAction,
+    /// Only apply to these models (`name` or `prefix*`); empty means all
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Only apply to these tenants (API key owners); empty means all
+    #[serde(default)]
+    pub tenants: Vec<String>,
+    /// e.g. `match: { any: [{ keywords: [a, b] }, { regex: "x+" }] }`
+    #[serde(
+        rename = "match",
+        deserialize_with = "serde_yaml::with::singleton_map_recursive::deserialize"
+    )]
+    pub condition: RuleCondition,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Refuse the request with 403
+    Block,
+    /// Forward, but mark the response and audit record
+    Flag,
+    /// Forward and skip the remaining rules
+    Allow,
+}
+
+/// Matched against the normalized prompt text.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleCondition {
+    /// Any of these words or phrases
+    Keywords(Vec<String>),
+    Regex(String),
+    All(Vec<RuleCondition>),
+    Any(Vec<RuleCondition>),
+    Not(Box<RuleCondition>),
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct DlpConfig {
+    #[serde(default)]
+    pub patterns: Vec<DlpPattern>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DlpPattern {
+    /// A built-in detector (`email`, `credit_card`, `iban`, `aws_access_key`,
+    /// `api_key`, `private_key`) unless `regex` is set
+    pub name: String,
+    #[serde(default)]
+    pub regex: Option<String>,
+    pub action: DlpAction,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DlpAction {
+    /// Refuse the request with 403
+    Block,
+    /// Replace the match before forwarding
+    Mask,
+    /// Forward unchanged, but log and audit the finding
+    Log,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    /// File the audit records are appended to
+    pub path: String,
+    /// How the prompt is recorded (`off`, `hash`, `truncate`, `redact`)
+    #[serde(default)]
+    pub prompt: AuditBodyMode,
+    /// How the response body is recorded
+    #[serde(default)]
+    pub response: AuditBodyMode,
+    /// Characters kept by `truncate`
+    #[serde(default = "default_audit_truncate")]
+    pub truncate_chars: usize,
+    /// Rotate once the file reaches this size
+    #[serde(default)]
+    pub rotate_bytes: Option<u64>,
+    /// Rotate after this long, e.g. `24h`
+    #[serde(default)]
+    pub rotate_interval: Option<String>,
+    /// Rotated files to keep; older ones are deleted. Unset keeps all.
+    #[serde(default)]
+    pub keep_files: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditBodyMode {
+    /// Not recorded
+    #[default]
+    Off,
+    /// SHA-256 hex digest
+    Hash,
+    /// The first `truncate_chars` characters
+    Truncate,
+    /// A placeholder noting that a body was present
+    Redact,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaConfig {
+    /// Tokens per rolling 24 hours for every client; 0 means unlimited
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    /// Tokens per rolling 30 days for every client; 0 means unlimited
+    #[serde(default)]
+    pub monthly_tokens: Option<u64>,
+    /// Overrides keyed by client (`sub:<subject>`, `key:<id>`, `ip:<addr>`)
+    #[serde(default)]
+    pub clients: HashMap<String, QuotaLimit>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaLimit {
+    /// Tokens per rolling 24 hours; 0 means unlimited
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+    /// Tokens per rolling 30 days; 0 means unlimited
+    #[serde(default)]
+    pub monthly_tokens: Option<u64>,
+}
+
+impl QuotaConfig {
+    pub fn default_limit(&self) -> QuotaLimit {
+        QuotaLimit {
+            daily_tokens: self.daily_tokens,
+            monthly_tokens: self.monthly_tokens,
+        }
+    }
+}
+
+impl QuotaLimit {
+    pub fn is_limited(&self) -> bool {
+        self.daily_tokens.unwrap_or(0) > 0 || self.monthly_tokens.unwrap_or(0) > 0
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct ClassLimit {
+    pub per_sec: u32,
+    #[serde(default)]
+    pub burst: u32,
 }
 
 #[derive(Debug, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Auth {
     #[serde(default)]
     pub oidc_providers: Vec<OidcProvider>,
-    pub cloudflare_jwt_secret: Option<String>,
+    /// Require a valid Cloudflare Access assertion on every request
+    #[serde(default)]
+    pub cloudflare_access: Option<CloudflareAccess>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CloudflareAccess {
+    /// `myteam` or `myteam.cloudflareaccess.com`
+    pub team_domain: String,
+    /// Application Audience (AUD) tag
+    pub aud: String,
+    /// Override for `https://<team_domain>/cdn-cgi/access/certs`
+    #[serde(default)]
+    pub certs_url: Option<String>,
+    #[serde(default = "default_clock_skew")]
+    pub clock_skew_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct OidcProvider {
     pub name: String,
     pub issuer_url: String,
-    #[allow(dead_code)]
     pub audience: String,
+    /// Fetch keys from here instead of the discovery document's `jwks_uri`
+    #[serde(default)]
+    pub jwks_uri: Option<String>,
+    /// Leeway applied to `exp` and `nbf`
+    #[serde(default = "default_clock_skew")]
+    pub clock_skew_secs: u64,
+    /// Accepted signing algorithms; empty means any asymmetric algorithm
+    #[serde(default)]
+    pub algorithms: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Tls {
     pub cert_path: String,
     pub key_path: String,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Backend {
     pub name: String,
     pub protocol: String,
     pub address: String,
-    #[allow(dead_code)]
+    /// Path prefixes (`/api`) or host-qualified prefixes (`api.example.com/v1`)
+    /// routed to this backend by the HTTP gateways.
+    #[serde(default)]
     pub routes: Vec<String>,
+    /// Active probe; unhealthy backends are not picked
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Attempts in total, including the first
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Upstream statuses worth retrying; connect errors always are
+    #[serde(default = "default_retry_statuses")]
+    pub statuses: Vec<u16>,
+    /// Backoff before the first retry; doubles for each one after, with jitter
+    #[serde(default = "default_base_backoff")]
+    pub base_backoff: String,
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: String,
+    /// Retries may add at most this share of requests, over 10 seconds
+    #[serde(default = "default_budget_percent")]
+    pub budget_percent: u32,
+    /// Retries per second allowed regardless of the budget
+    #[serde(default = "default_min_retries_per_sec")]
+    pub min_retries_per_sec: u32,
+}
+
+/// The config file is always reloaded on SIGHUP.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReloadConfig {
+    /// Also reload when the file's modification time changes
+    #[serde(default)]
+    pub watch: bool,
+    /// How often `watch` checks the file
+    #[serde(default = "default_watch_interval")]
+    pub interval: String,
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        Self {
+            watch: false,
+            interval: default_watch_interval(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OutlierDetection {
+    /// 5xx responses, connect failures or timeouts in a row before ejecting
+    #[serde(default = "default_consecutive_errors")]
+    pub consecutive_errors: u32,
+    /// First ejection; each repeat doubles it
+    #[serde(default = "default_base_ejection")]
+    pub base_ejection: String,
+    #[serde(default = "default_max_ejection")]
+    pub max_ejection: String,
+    /// Share of a pool that may be ejected at once
+    #[serde(default = "default_max_ejected_percent")]
+    pub max_ejected_percent: u32,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheck {
+    /// Defaults to the backend's protocol
+    #[serde(default)]
+    pub kind: Option<ProbeKind>,
+    /// Path for `http` probes
+    #[serde(default = "default_health_path")]
+    pub path: String,
+    /// Status an `http` probe expects
+    #[serde(default = "default_expected_status")]
+    pub expected_status: u16,
+    /// Service name for `grpc` probes; empty means the whole server
+    #[serde(default)]
+    pub grpc_service: String,
+    /// Datagram a `udp` probe sends
+    #[serde(default)]
+    pub send: String,
+    /// Prefix the `udp` reply must start with; any reply if unset
+    #[serde(default)]
+    pub expect: Option<String>,
+    #[serde(default = "default_health_interval")]
+    pub interval: String,
+    #[serde(default = "default_health_timeout")]
+    pub timeout: String,
+    /// Consecutive passes before an unhealthy backend is used again
+    #[serde(default = "default_rise")]
+    pub rise: u32,
+    /// Consecutive failures before a backend is taken out
+    #[serde(default = "default_fall")]
+    pub fall: u32,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeKind {
+    /// GET `path`, expecting `expected_status`
+    Http,
+    /// GET `/api/version`
+    Ollama,
+    /// `grpc.health.v1.Health/Check`
+    Grpc,
+    /// TCP connect
+    Tcp,
+    /// Send `send`, wait for a reply
+    Udp,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ProxyConfig {
     #[serde(default = "default_upstream")]
     pub upstream: String,
@@ -89,6 +546,133 @@ pub struct ProxyConfig {
     pub max_num_ctx: u64,
     #[serde(default = "default_max_num_predict")]
     pub max_num_predict: i64,
+    /// Bounds on sampling options, in `options` or at the top level
+    #[serde(default)]
+    pub sampling: SamplingPolicy,
+    /// Limits on images in multimodal requests
+    #[serde(default)]
+    pub images: ImagePolicy,
+    /// Tool (function) calling policy for chat requests and responses
+    #[serde(default)]
+    pub tools: ToolPolicy,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ToolPolicy {
+    /// Tool names (`name` or `prefix*`) that may be offered; empty means any
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Tool names that may never be offered or called
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Tools per request
+    #[serde(default)]
+    pub max_tools: Option<usize>,
+    /// Serialized size of a single tool definition
+    #[serde(default)]
+    pub max_schema_bytes: Option<usize>,
+    /// What to do with response `tool_calls` naming a tool that was not
+    /// offered or is not allowed
+    #[serde(default)]
+    pub calls: ToolCallAction,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolCallAction {
+    /// Pass responses through unchecked
+    #[default]
+    Pass,
+    /// Remove the offending calls
+    Strip,
+    /// Replace the response with an error
+    Block,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ImagePolicy {
+    /// `false` refuses any request carrying images
+    #[serde(default = "default_true")]
+    pub allow: bool,
+    /// Images per request
+    #[serde(default)]
+    pub max_count: Option<usize>,
+    /// Decoded size of a single image
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+    /// Decoded size of all images in a request
+    #[serde(default)]
+    pub max_total_bytes: Option<usize>,
+    /// Formats accepted, sniffed from the image data; empty means any
+    #[serde(default)]
+    pub formats: Vec<ImageFormat>,
+}
+
+impl Default for ImagePolicy {
+    fn default() -> Self {
+        Self {
+            allow: true,
+            max_count: None,
+            max_bytes: None,
+            max_total_bytes: None,
+            formats: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SamplingPolicy {
+    /// `reject` out-of-range values, or `clamp` them into range
+    #[serde(default)]
+    pub mode: SamplingMode,
+    /// Options that may not be set at all
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(default)]
+    pub temperature: Option<Bound>,
+    #[serde(default)]
+    pub top_p: Option<Bound>,
+    #[serde(default)]
+    pub top_k: Option<Bound>,
+    /// Combined with `max_num_ctx`; the lower maximum applies
+    #[serde(default)]
+    pub num_ctx: Option<Bound>,
+    /// Combined with `max_num_predict`; the lower maximum applies
+    #[serde(default)]
+    pub num_predict: Option<Bound>,
+    #[serde(default)]
+    pub num_gpu: Option<Bound>,
+    #[serde(default)]
+    pub num_thread: Option<Bound>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Bound {
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingMode {
+    #[default]
+    Reject,
+    Clamp,
 }
 
 fn default_upstream() -> String {
@@ -103,6 +687,81 @@ fn default_endpoint_denylist() -> Vec<String> {
         "DELETE /api/delete".to_string(),
     ]
 }
+fn default_true() -> bool {
+    true
+}
+fn default_clock_skew() -> u64 {
+    60
+}
+fn default_cache_ttl() -> String {
+    "1h".to_string()
+}
+fn default_max_attempts() -> u32 {
+    3
+}
+fn default_retry_statuses() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+fn default_base_backoff() -> String {
+    "25ms".to_string()
+}
+fn default_max_backoff() -> String {
+    "250ms".to_string()
+}
+fn default_budget_percent() -> u32 {
+    20
+}
+fn default_min_retries_per_sec() -> u32 {
+    10
+}
+fn default_watch_interval() -> String {
+    "2s".into()
+}
+fn default_consecutive_errors() -> u32 {
+    5
+}
+fn default_base_ejection() -> String {
+    "30s".to_string()
+}
+fn default_max_ejection() -> String {
+    "5m".to_string()
+}
+fn default_max_ejected_percent() -> u32 {
+    50
+}
+fn default_health_path() -> String {
+    "/".to_string()
+}
+fn default_expected_status() -> u16 {
+    200
+}
+fn default_health_interval() -> String {
+    "10s".to_string()
+}
+fn default_health_timeout() -> String {
+    "2s".to_string()
+}
+fn default_rise() -> u32 {
+    2
+}
+fn default_fall() -> u32 {
+    3
+}
+fn default_max_queue() -> usize {
+    100
+}
+fn default_queue_timeout() -> String {
+    "30s".to_string()
+}
+fn default_cache_entries() -> usize {
+    10_000
+}
+fn default_cache_entry_bytes() -> usize {
+    4 * 1024 * 1024
+}
+fn default_audit_truncate() -> usize {
+    256
+}
 fn default_max_body() -> usize {
     1_048_576
 }
@@ -119,14 +778,42 @@ fn default_max_num_predict() -> i64 {
 impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
         let p = path.as_ref();
-        let file = File::open(p).map_err(|e| ConfigError::Io {
+        let text = std::fs::read_to_string(p).map_err(|e| ConfigError::Io {
             path: p.display().to_string(),
             source: e,
         })?;
-        let reader = BufReader::new(file);
-        let cfg = serde_yaml::from_reader(reader)?;
-        Ok(cfg)
+        validate::parse(&text).map_err(ConfigError::Invalid)
+    }
+
+    /// `https_port`, else `http_port + 1`; `None` if that is out of range.
+    pub fn https_listen_port(&self) -> Option<u16> {
+        self.https_port.or(self.http_port.checked_add(1))
+    }
+
+    pub fn grpc_listen_port(&self) -> u16 {
+        self.grpc_port.unwrap_or(50051)
+    }
+
+    pub fn http_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        listen_addr(self.http_bind_addr.as_deref(), self.http_port)
     }
+
+    pub fn https_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        listen_addr(
+            self.https_bind_addr.as_deref(),
+            self.https_listen_port().unwrap_or_default(),
+        )
+    }
+
+    pub fn grpc_addr(&self) -> Result<SocketAddr, AddrParseError> {
+        listen_addr(self.grpc_bind_addr.as_deref(), self.grpc_listen_port())
+    }
+}
+
+/// Listeners bind to `127.0.0.1` unless configured otherwise.
+fn listen_addr(bind: Option<&str>, port: u16) -> Result<SocketAddr, AddrParseError> {
+    let ip: IpAddr = bind.unwrap_or("127.0.0.1").parse()?;
+    Ok(SocketAddr::new(ip, port))
 }
 
 impl Default for ProxyConfig {
@@ -140,6 +827,9 @@ impl Default for ProxyConfig {
             max_prompt_chars: default_max_prompt(),
             max_num_ctx: default_max_num_ctx(),
             max_num_predict: default_max_num_predict(),
+            sampling: SamplingPolicy::default(),
+            images: ImagePolicy::default(),
+            tools: ToolPolicy::default(),
         }
     }
 }
@@ -0,0 +1,309 @@
+// src/content_rules.rs
+
+use crate::config::{ContentRule, RuleAction, RuleCondition};
+use regex::Regex;
+use serde_json::Value;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Error)]
+pub enum ContentRuleError {
+    #[error("content rule '{rule}': {source}")]
+    Regex { rule: String, source: regex::Error },
+    #[error("content rule '{0}': empty keyword list")]
+    NoKeywords(String),
+}
+
+/// Fold text so look-alike spellings compare equal: NFKC (fullwidth and
+/// ligature forms), invisible characters removed, lowercased, and common
+/// Cyrillic/Greek homoglyphs mapped to their Latin twins.
+pub fn normalize(text: &str) -> String {
+    text.nfkc()
+        .filter(|c| !is_invisible(*c))
+        .flat_map(char::to_lowercase)
+        .map(fold_homoglyph)
+        .collect()
+}
+
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}' | '\u{180E}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}'
+    )
+}
+
+fn fold_homoglyph(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'в' | 'β' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ε' | 'ё' => 'e',
+        'ɡ' => 'g',
+        'һ' | 'н' => 'h',
+        'і' | 'ι' | 'ı' | 'ї' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'м' => 'm',
+        'п' | 'η' => 'n',
+        'о' | 'ο' | 'σ' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' | 'ս' => 'u',
+        'ν' | 'ѵ' => 'v',
+        'ԝ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        'ʐ' => 'z',
+        _ => c,
+    }
+}
+
+enum Matcher {
+    Keywords(Vec<String>),
+    Regex(Box<Regex>),
+    All(Vec<Matcher>),
+    Any(Vec<Matcher>),
+    Not(Box<Matcher>),
+}
+
+impl Matcher {
+    fn build(rule: &str, c: &RuleCondition) -> Result<Self, ContentRuleError> {
+        let all = |cs: &[RuleCondition]| -> Result<Vec<Matcher>, ContentRuleError> {
+            cs.iter().map(|c| Matcher::build(rule, c)).collect()
+        };
+        Ok(match c {
+            RuleCondition::Keywords(words) => {
+                let words: Vec<String> = words
+                    .iter()
+                    .map(|w| normalize(w.trim()))
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                if words.is_empty() {
+                    return Err(ContentRuleError::NoKeywords(rule.to_string()));
+                }
+                Matcher::Keywords(words)
+            }
+            RuleCondition::Regex(re) => {
+                Matcher::Regex(Box::new(Regex::new(re).map_err(|source| {
+                    ContentRuleError::Regex {
+                        rule: rule.to_string(),
+                        source,
+                    }
+                })?))
+            }
+            RuleCondition::All(cs) => Matcher::All(all(cs)?),
+            RuleCondition::Any(cs) => Matcher::Any(all(cs)?),
+            RuleCondition::Not(c) => Matcher::Not(Box::new(Matcher::build(rule, c)?)),
+        })
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Matcher::Keywords(words) => words.iter().any(|w| contains_word(text, w)),
+            Matcher::Regex(re) => re.is_match(text),
+            Matcher::All(ms) => ms.iter().all(|m| m.matches(text)),
+            Matcher::Any(ms) => ms.iter().any(|m| m.matches(text)),
+            Matcher::Not(m) => !m.matches(text),
+        }
+    }
+}
+
+/// `needle` occurs in `haystack` on word boundaries.
+fn contains_word(haystack: &str, needle: &str) -> bool {
+    let boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    haystack.match_indices(needle).any(|(i, _)| {
+        boundary(haystack[..i].chars().next_back())
+            && boundary(haystack[i + needle.len()..].chars().next())
+    })
+}
+
+struct Rule {
+    name: String,
+    action: RuleAction,
+    models: Vec<String>,
+    tenants: Vec<String>,
+    matcher: Matcher,
+}
+
+impl Rule {
+    fn applies(&self, model: Option<&str>, tenant: Option<&str>) -> bool {
+        let model_ok = self.models.is_empty()
+            || model.is_some_and(|m| {
+                self.models.iter().any(|p| match p.strip_suffix('*') {
+                    Some(prefix) => m.starts_with(prefix),
+                    None => m == p,
+                })
+            });
+        let tenant_ok =
+            self.tenants.is_empty() || tenant.is_some_and(|t| self.tenants.iter().any(|x| x == t));
+        model_ok && tenant_ok
+    }
+}
+
+/// A rule that matched a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    pub rule: String,
+    pub action: RuleAction,
+}
+
+impl std::fmt::Display for RuleMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = match self.action {
+            RuleAction::Block => "block",
+            RuleAction::Flag => "flag",
+            RuleAction::Allow => "allow",
+        };
+        write!(f, "{}:{}", self.rule, action)
+    }
+}
+
+/// Ordered content rules. `flag` rules are recorded and evaluation goes on;
+/// the first matching `allow` or `block` rule ends it.
+#[derive(Default)]
+pub struct ContentRules {
+    rules: Vec<Rule>,
+}
+
+impl ContentRules {
+    pub fn new(cfg: &[ContentRule]) -> Result<Self, ContentRuleError> {
+        let rules = cfg
+            .iter()
+            .map(|r| {
+                Ok(Rule {
+                    name: r.name.clone(),
+                    action: r.action,
+                    models: r.models.clone(),
+                    tenants: r.tenants.clone(),
+                    matcher: Matcher::build(&r.name, &r.condition)?,
+                })
+            })
+            .collect::<Result<_, ContentRuleError>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Evaluate the rules against the prompt text of a request document.
+    pub fn evaluate(
+        &self,
+        doc: &Value,
+        model: Option<&str>,
+        tenant: Option<&str>,
+    ) -> Vec<RuleMatch> {
+        let mut matched = Vec::new();
+        let mut applicable = self
+            .rules
+            .iter()
+            .filter(|r| r.applies(model, tenant))
+            .peekable();
+        if applicable.peek().is_none() {
+            return matched;
+        }
+        let text = normalize(&prompt_texts(doc).join("\n"));
+        for rule in applicable {
+            if !rule.matcher.matches(&text) {
+                continue;
+            }
+            matched.push(RuleMatch {
+                rule: rule.name.clone(),
+                action: rule.action,
+            });
+            if rule.action != RuleAction::Flag {
+                break;
+            }
+        }
+        matched
+    }
+}
+
+/// `prompt`, `system` and every chat message's text.
+fn prompt_texts(doc: &Value) -> Vec<&str> {
+    let mut out = Vec::new();
+    for key in ["prompt", "system"] {
+        if let Some(s) = doc.get(key).and_then(Value::as_str) {
+            out.push(s);
+        }
+    }
+    for m in doc
+        .get("messages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        match m.get("content") {
+            Some(Value::String(s)) => out.push(s),
+            Some(Value::Array(parts)) => out.extend(
+                parts
+                    .iter()
+                    .filter_map(|p| p.get("text").and_then(Value::as_str)),
+            ),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization_defeats_lookalikes() {
+        // Fullwidth, Cyrillic "о", zero-width space and mixed case.
+        assert_eq!(normalize("ＢＯＭＢ"), "bomb");
+        assert_eq!(normalize("Bоm\u{200B}b"), "bomb");
+        assert!(contains_word("how to build a bomb?", "bomb"));
+        assert!(!contains_word("bombastic", "bomb"));
+    }
+
+    #[test]
+    fn test_rule_order_scope_and_combinations() {
+        let cfg: Vec<ContentRule> = serde_yaml::from_str(
+            r#"
+- name: research-exception
+  action: allow
+  tenants: [red-team]
+  match: { keywords: [exploit] }
+- name: exploit-dev
+  action: block
+  models: ["llama3*"]
+  match:
+    all:
+      - keywords: [exploit, payload]
+      - not: { keywords: [ctf] }
+- name: competitor
+  action: flag
+  match: { regex: "acme\\s+corp" }
+"#,
+        )
+        .unwrap();
+        let rules = ContentRules::new(&cfg).unwrap();
+        let doc = serde_json::json!({
+            "messages": [{"role": "user", "content": "Write an ЕXPLOIT for ACME  Corp"}]
+        });
+        let names = |m: Vec<RuleMatch>| m.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(rules.evaluate(&doc, Some("llama3:8b"), None)),
+            ["exploit-dev:block"]
+        );
+        // Scoped to other models: only the flag applies.
+        assert_eq!(
+            names(rules.evaluate(&doc, Some("mistral"), None)),
+            ["competitor:flag"]
+        );
+        // An earlier allow rule short-circuits the block.
+        assert_eq!(
+            names(rules.evaluate(&doc, Some("llama3:8b"), Some("red-team"))),
+            ["research-exception:allow"]
+        );
+        let ctf = serde_json::json!({"prompt": "ctf exploit"});
+        assert!(rules.evaluate(&ctf, Some("llama3"), None).is_empty());
+    }
+}
@@ -11,7 +11,7 @@ use tonic::{
     metadata::{KeyAndValueRef, MetadataMap},
     service::Interceptor,
     transport::{Channel, Server},
-    Request, Response, Status,
+    Code, Request, Response, Status,
 };
 
 /// Interceptor that forwards all incoming metadata to the downstream request.
@@ -40,6 +40,14 @@ impl Interceptor for MetadataForwardInterceptor {
     }
 }
 
+/// Status codes that count against a backend for outlier detection.
+fn backend_failure(code: Code) -> bool {
+    matches!(
+        code,
+        Code::Unavailable | Code::DeadlineExceeded | Code::Internal | Code::Unknown
+    )
+}
+
 /// Simple gRPC proxy: looks up a backend by metadata then forwards the Echo RPC.
 #[derive(Clone)]
 pub struct EchoProxy {
@@ -68,7 +76,10 @@ impl Echo for EchoProxy {
             .map_err(|e| Status::internal(format!("Invalid URL: {}", e)))?
             .connect()
             .await
-            .map_err(|e| Status::internal(format!("Channel error: {}", e)))?;
+            .map_err(|e| {
+                self.registry.report(&target, false);
+                Status::internal(format!("Channel error: {}", e))
+            })?;
 
         // 4) Forward incoming metadata
         let interceptor = MetadataForwardInterceptor {
@@ -77,8 +88,12 @@ impl Echo for EchoProxy {
         let mut client = EchoClient::with_interceptor(channel, interceptor);
 
         // 5) Forward the request and return the response
-        let response = client.echo(req.into_inner()).await?.into_inner();
-        Ok(Response::new(response))
+        let result = client.echo(req.into_inner()).await;
+        self.registry.report(
+            &target,
+            !matches!(&result, Err(s) if backend_failure(s.code())),
+        );
+        Ok(Response::new(result?.into_inner()))
     }
 }
 
@@ -0,0 +1,311 @@
+// src/health.rs
+
+use crate::{
+    backend_registry::BackendRegistry,
+    config::{Backend, HealthCheck, ProbeKind},
+};
+use log::{info, warn};
+use reqwest::Client;
+use std::{sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    net::{TcpStream, UdpSocket},
+    task::AbortHandle,
+};
+use tonic::transport::Channel;
+use tonic_health::pb::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+
+#[derive(Debug, Error)]
+pub enum HealthError {
+    #[error("backend '{0}': invalid health check {1} '{2}': {3}")]
+    Duration(String, &'static str, String, String),
+    #[error("backend '{0}': no health check kind for protocol '{1}'")]
+    Protocol(String, String),
+}
+
+/// Up/down state with rise and fall thresholds. Backends start healthy.
+#[derive(Debug)]
+struct Tracker {
+    healthy: bool,
+    streak: u32,
+    rise: u32,
+    fall: u32,
+}
+
+impl Tracker {
+    fn new(rise: u32, fall: u32) -> Self {
+        Self {
+            healthy: true,
+            streak: 0,
+            rise: rise.max(1),
+            fall: fall.max(1),
+        }
+    }
+
+    /// Record a probe result. Returns the new state when it changes.
+    fn record(&mut self, ok: bool) -> Option<bool> {
+        if ok == self.healthy {
+            self.streak = 0;
+            return None;
+        }
+        self.streak += 1;
+        let needed = if ok { self.rise } else { self.fall };
+        if self.streak < needed {
+            return None;
+        }
+        self.healthy = ok;
+        self.streak = 0;
+        Some(ok)
+    }
+}
+
+/// Parse a health check `interval` or `timeout`, which must not be zero.
+pub fn period(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s) {
+        Ok(d) if d.is_zero() => Err("must be greater than zero".to_string()),
+        Ok(d) => Ok(d),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+struct Check {
+    service: String,
+    address: String,
+    kind: ProbeKind,
+    cfg: HealthCheck,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Check {
+    fn new(be: &Backend, cfg: &HealthCheck) -> Result<Self, HealthError> {
+        let duration = |what, s: &str| {
+            period(s).map_err(|e| HealthError::Duration(be.name.clone(), what, s.to_string(), e))
+        };
+        let kind = match (cfg.kind, be.protocol.as_str()) {
+            (Some(kind), _) => kind,
+            (None, "http" | "https") => ProbeKind::Http,
+            (None, "grpc") => ProbeKind::Grpc,
+            (None, "tcp") => ProbeKind::Tcp,
+            (None, "udp") => ProbeKind::Udp,
+            (None, other) => return Err(HealthError::Protocol(be.name.clone(), other.to_string())),
+        };
+        Ok(Self {
+            service: be.name.clone(),
+            address: be.address.clone(),
+            kind,
+            cfg: cfg.clone(),
+            interval: duration("interval", &cfg.interval)?,
+            timeout: duration("timeout", &cfg.timeout)?,
+        })
+    }
+
+    async fn probe(&self, client: &Client) -> Result<(), String> {
+        let base = self.address.trim_end_matches('/');
+        match self.kind {
+            ProbeKind::Http => {
+                let res = client
+                    .get(format!("{}{}", base, self.cfg.path))
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if res.status().as_u16() != self.cfg.expected_status {
+                    return Err(format!("status {}", res.status().as_u16()));
+                }
+                Ok(())
+            }
+            ProbeKind::Ollama => {
+                let res = client
+                    .get(format!("{}/api/version", base))
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if !res.status().is_success() {
+                    return Err(format!("status {}", res.status().as_u16()));
+                }
+                let body = res.bytes().await.map_err(|e| e.to_string())?;
+                let doc: serde_json::Value =
+                    serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+                doc.get("version")
+                    .map(|_| ())
+                    .ok_or_else(|| "no version in response".to_string())
+            }
+            ProbeKind::Grpc => {
+                let channel = Channel::from_shared(self.address.clone())
+                    .map_err(|e| e.to_string())?
+                    .connect()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let res = HealthClient::new(channel)
+                    .check(HealthCheckRequest {
+                        service: self.cfg.grpc_service.clone(),
+                    })
+                    .await
+                    .map_err(|s| s.message().to_string())?;
+                match res.into_inner().status() {
+                    ServingStatus::Serving => Ok(()),
+                    status => Err(format!("status {}", status.as_str_name())),
+                }
+            }
+            ProbeKind::Tcp => TcpStream::connect(&self.address)
+                .await
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            ProbeKind::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0")
+                    .await
+                    .map_err(|e| e.to_string())?;
+                socket
+                    .connect(&self.address)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                socket
+                    .send(self.cfg.send.as_bytes())
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let mut buf = [0u8; 2048];
+                let n = socket.recv(&mut buf).await.map_err(|e| e.to_string())?;
+                match &self.cfg.expect {
+                    Some(want) if !buf[..n].starts_with(want.as_bytes()) => {
+                        Err("unexpected reply".to_string())
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Probes every backend with a `health_check` and records the result in
+/// the registry.
+pub struct HealthChecker {
+    checks: Vec<Check>,
+    client: Client,
+    registry: Arc<BackendRegistry>,
+}
+
+impl HealthChecker {
+    pub fn new(backends: &[Backend], registry: Arc<BackendRegistry>) -> Result<Self, HealthError> {
+        let checks = backends
+            .iter()
+            .filter_map(|be| be.health_check.as_ref().map(|hc| Check::new(be, hc)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            checks,
+            client: Client::new(),
+            registry,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.checks.len()
+    }
+
+    /// Run each check on its own task until its handle is aborted. A
+    /// backend picks up from the state already in the registry.
+    pub fn spawn(self) -> Vec<AbortHandle> {
+        let mut tasks = Vec::with_capacity(self.checks.len());
+        for check in self.checks {
+            let client = self.client.clone();
+            let registry = self.registry.clone();
+            let task = tokio::spawn(async move {
+                let mut tracker = Tracker::new(check.cfg.rise, check.cfg.fall);
+                if let Some(healthy) = registry.is_healthy(&check.service, &check.address) {
+                    tracker.healthy = healthy;
+                }
+                let mut ticker = tokio::time::interval(check.interval);
+                loop {
+                    ticker.tick().await;
+                    let result = tokio::time::timeout(check.timeout, check.probe(&client))
+                        .await
+                        .unwrap_or_else(|_| Err("timed out".to_string()));
+                    match tracker.record(result.is_ok()) {
+                        Some(true) => {
+                            info!("backend {} ({}) is healthy", check.service, check.address)
+                        }
+                        Some(false) => warn!(
+                            "backend {} ({}) is unhealthy: {}",
+                            check.service,
+                            check.address,
+                            result
+                                .as_ref()
+                                .err()
+                                .map(String::as_str)
+                                .unwrap_or_default()
+                        ),
+                        None => {}
+                    }
+                    registry.set_health(
+                        &check.service,
+                        &check.address,
+                        tracker.healthy,
+                        result.err(),
+                    );
+                }
+            });
+            tasks.push(task.abort_handle());
+        }
+        tasks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(protocol: &str, address: &str) -> Check {
+        let be: Backend = serde_yaml::from_str(&format!(
+            "{{ name: svc, protocol: {}, address: '{}',
+                health_check: {{ send: ping, expect: ping }} }}",
+            protocol, address
+        ))
+        .unwrap();
+        Check::new(&be, be.health_check.as_ref().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_zero_period() {
+        let be: Backend = serde_yaml::from_str(
+            "{ name: svc, protocol: tcp, address: '127.0.0.1:1',
+               health_check: { interval: 0s } }",
+        )
+        .unwrap();
+        assert!(matches!(
+            Check::new(&be, be.health_check.as_ref().unwrap()),
+            Err(HealthError::Duration(_, "interval", _, _))
+        ));
+    }
+
+    #[test]
+    fn test_rise_and_fall() {
+        let mut t = Tracker::new(2, 3);
+        assert_eq!(t.record(false), None);
+        assert_eq!(t.record(true), None);
+        assert_eq!(t.record(false), None);
+        assert_eq!(t.record(false), None);
+        assert_eq!(t.record(false), Some(false));
+        assert_eq!(t.record(true), None);
+        assert_eq!(t.record(true), Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_probes() {
+        let client = Client::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        assert!(check("tcp", &addr).probe(&client).await.is_ok());
+        drop(listener);
+        assert!(check("tcp", &addr).probe(&client).await.is_err());
+
+        let echo = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = echo.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let (n, peer) = echo.recv_from(&mut buf).await.unwrap();
+            echo.send_to(&buf[..n], peer).await.unwrap();
+        });
+        assert!(check("udp", &addr).probe(&client).await.is_ok());
+    }
+}
@@ -0,0 +1,221 @@
+// src/images.rs
+
+use crate::config::{ImageFormat, ImagePolicy};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ImageError {
+    #[error("images are not allowed")]
+    NotAllowed,
+    #[error("too many images: {count} (limit {limit})")]
+    TooMany { count: usize, limit: usize },
+    #[error("image {index} is {size} bytes (limit {limit})")]
+    TooLarge {
+        index: usize,
+        size: usize,
+        limit: usize,
+    },
+    #[error("images total {size} bytes (limit {limit})")]
+    TotalTooLarge { size: usize, limit: usize },
+    #[error("image {0} is not valid base64")]
+    Invalid(usize),
+    #[error("image {0} is not an allowed format")]
+    Format(usize),
+    #[error("image {0} must be inline base64 data to be checked, not a URL")]
+    Remote(usize),
+}
+
+impl ImageError {
+    /// Label for the policy rejection counter.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ImageError::NotAllowed => "images_not_allowed",
+            ImageError::TooMany { .. } => "too_many_images",
+            ImageError::TooLarge { .. } | ImageError::TotalTooLarge { .. } => "image_too_large",
+            ImageError::Invalid(_) | ImageError::Remote(_) => "invalid_image",
+            ImageError::Format(_) => "image_format",
+        }
+    }
+}
+
+/// The format of decoded image data, from its magic bytes.
+pub fn sniff(data: &[u8]) -> Option<ImageFormat> {
+    match data {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageFormat::Png),
+        [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageFormat::Gif),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+        _ => None,
+    }
+}
+
+enum Image<'a> {
+    Base64(&'a str),
+    Url,
+}
+
+/// Native `images` (top level and per message) and OpenAI `image_url`
+/// content parts, in request order.
+fn collect(doc: &Value) -> Vec<Image<'_>> {
+    let mut out = Vec::new();
+    fn base64<'a>(v: Option<&'a Value>, out: &mut Vec<Image<'a>>) {
+        out.extend(
+            v.and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(Image::Base64),
+        )
+    }
+    base64(doc.get("images"), &mut out);
+    for m in doc
+        .get("messages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        base64(m.get("images"), &mut out);
+        let parts = m.get("content").and_then(Value::as_array);
+        for p in parts.into_iter().flatten() {
+            if p.get("type").and_then(Value::as_str) != Some("image_url") {
+                continue;
+            }
+            let url = p
+                .pointer("/image_url/url")
+                .or_else(|| p.get("image_url"))
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match url
+                .strip_prefix("data:")
+                .and_then(|rest| rest.split_once(";base64,"))
+            {
+                Some((_, data)) => out.push(Image::Base64(data)),
+                None => out.push(Image::Url),
+            }
+        }
+    }
+    out
+}
+
+/// Check the images in a request document against `policy`. Returns how
+/// many there were.
+pub fn check_images(policy: &ImagePolicy, doc: &Value) -> Result<usize, ImageError> {
+    let images = collect(doc);
+    if images.is_empty() {
+        return Ok(0);
+    }
+    if !policy.allow {
+        return Err(ImageError::NotAllowed);
+    }
+    if let Some(limit) = policy.max_count.filter(|l| images.len() > *l) {
+        return Err(ImageError::TooMany {
+            count: images.len(),
+            limit,
+        });
+    }
+    // Only decode when size or format matters.
+    if policy.max_bytes.is_none() && policy.max_total_bytes.is_none() && policy.formats.is_empty() {
+        return Ok(images.len());
+    }
+    let mut total = 0;
+    for (index, image) in images.iter().enumerate() {
+        let Image::Base64(encoded) = image else {
+            return Err(ImageError::Remote(index));
+        };
+        let data = STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| ImageError::Invalid(index))?;
+        if let Some(limit) = policy.max_bytes.filter(|l| data.len() > *l) {
+            return Err(ImageError::TooLarge {
+                index,
+                size: data.len(),
+                limit,
+            });
+        }
+        let allowed =
+            policy.formats.is_empty() || sniff(&data).is_some_and(|f| policy.formats.contains(&f));
+        if !allowed {
+            return Err(ImageError::Format(index));
+        }
+        total += data.len();
+    }
+    if let Some(limit) = policy.max_total_bytes.filter(|l| total > *l) {
+        return Err(ImageError::TotalTooLarge { size: total, limit });
+    }
+    Ok(images.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const GIF: &[u8] = b"GIF89a\x01\0\x01\0";
+
+    #[test]
+    fn test_counts_sizes_and_formats() {
+        let png = STANDARD.encode(PNG);
+        let gif = STANDARD.encode(GIF);
+        let doc = json!({
+            "images": [png],
+            "messages": [
+                {"role": "user", "content": "look", "images": [gif]},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "and"},
+                    {"type": "image_url", "image_url": {"url": format!("data:image/png;base64,{}", png)}}
+                ]}
+            ]
+        });
+        let policy = ImagePolicy {
+            max_count: Some(3),
+            max_bytes: Some(16),
+            max_total_bytes: Some(64),
+            formats: vec![ImageFormat::Png, ImageFormat::Gif],
+            ..ImagePolicy::default()
+        };
+        assert_eq!(check_images(&policy, &doc), Ok(3));
+
+        let strict = |p: ImagePolicy| check_images(&p, &doc).unwrap_err();
+        assert_eq!(
+            strict(ImagePolicy {
+                allow: false,
+                ..ImagePolicy::default()
+            }),
+            ImageError::NotAllowed
+        );
+        assert_eq!(
+            strict(ImagePolicy {
+                max_count: Some(2),
+                ..ImagePolicy::default()
+            }),
+            ImageError::TooMany { count: 3, limit: 2 }
+        );
+        assert_eq!(
+            strict(ImagePolicy {
+                max_total_bytes: Some(30),
+                ..ImagePolicy::default()
+            })
+            .reason(),
+            "image_too_large"
+        );
+        assert_eq!(
+            strict(ImagePolicy {
+                formats: vec![ImageFormat::Png],
+                ..ImagePolicy::default()
+            }),
+            ImageError::Format(1)
+        );
+
+        let bad = json!({"images": ["not base64!"]});
+        assert_eq!(check_images(&policy, &bad), Err(ImageError::Invalid(0)));
+        let remote = json!({"messages": [{"content": [
+            {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+        ]}]});
+        assert_eq!(check_images(&policy, &remote), Err(ImageError::Remote(0)));
+        assert_eq!(check_images(&ImagePolicy::default(), &remote), Ok(1));
+        assert!(check_images(&policy, &json!({"prompt": "no images"})).is_ok());
+    }
+}
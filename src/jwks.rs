@@ -0,0 +1,319 @@
+// src/jwks.rs
+
+use jsonwebtoken::{
+    decode, decode_header, errors::ErrorKind, jwk::Jwk, Algorithm, DecodingKey, Validation,
+};
+use log::{debug, warn};
+use parking_lot::RwLock;
+use reqwest::Client as ReqwestClient;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// Why a bearer JWT was rejected. The `Display` text is returned to the
+/// client in the 401 body, so it names the failed check but no secrets.
+#[derive(Debug, Error)]
+pub enum JwtError {
+    #[error("malformed token")]
+    Malformed,
+    #[error("token issuer '{0}' is not trusted")]
+    UnknownIssuer(String),
+    #[error("signing key '{0}' not found in JWKS")]
+    UnknownKey(String),
+    #[error("signing algorithm {0:?} not allowed")]
+    AlgorithmNotAllowed(Algorithm),
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("token expired")]
+    Expired,
+    #[error("token not yet valid")]
+    NotYetValid,
+    #[error("invalid issuer")]
+    InvalidIssuer,
+    #[error("invalid audience")]
+    InvalidAudience,
+    #[error("missing claim '{0}'")]
+    MissingClaim(String),
+    #[error("key discovery failed: {0}")]
+    Discovery(String),
+}
+
+impl JwtError {
+    /// A fixed description, without the claim values of the token, for
+    /// response headers.
+    pub fn description(&self) -> &'static str {
+        match self {
+            JwtError::Malformed => "malformed token",
+            JwtError::UnknownIssuer(_) => "issuer not trusted",
+            JwtError::UnknownKey(_) => "signing key not found",
+            JwtError::AlgorithmNotAllowed(_) => "signing algorithm not allowed",
+            JwtError::InvalidSignature => "invalid signature",
+            JwtError::Expired => "token expired",
+            JwtError::NotYetValid => "token not yet valid",
+            JwtError::InvalidIssuer => "invalid issuer",
+            JwtError::InvalidAudience => "invalid audience",
+            JwtError::MissingClaim(_) => "missing claim",
+            JwtError::Discovery(_) => "key discovery failed",
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for JwtError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        match e.kind() {
+            ErrorKind::InvalidSignature => JwtError::InvalidSignature,
+            ErrorKind::ExpiredSignature => JwtError::Expired,
+            ErrorKind::ImmatureSignature => JwtError::NotYetValid,
+            ErrorKind::InvalidIssuer => JwtError::InvalidIssuer,
+            ErrorKind::InvalidAudience => JwtError::InvalidAudience,
+            ErrorKind::MissingRequiredClaim(c) => JwtError::MissingClaim(c.clone()),
+            _ => JwtError::Malformed,
+        }
+    }
+}
+
+/// Asymmetric algorithms accepted when a provider doesn't list its own.
+/// HMAC is never accepted: a JWKS only carries public keys.
+pub const DEFAULT_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Parse algorithm names from config, dropping anything unknown or symmetric.
+pub fn parse_algorithms(names: &[String]) -> Vec<Algorithm> {
+    if names.is_empty() {
+        return DEFAULT_ALGORITHMS.to_vec();
+    }
+    names
+        .iter()
+        .filter_map(|n| match Algorithm::from_str(n) {
+            Ok(a) if DEFAULT_ALGORITHMS.contains(&a) => Some(a),
+            _ => {
+                warn!("ignoring unsupported JWT algorithm '{}'", n);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Where a [`JwksCache`] finds its keys.
+#[derive(Debug, Clone)]
+pub enum JwksSource {
+    /// Resolve `jwks_uri` from `<issuer>/.well-known/openid-configuration`
+    Discovery { issuer: String },
+    /// Fetch the key set from a fixed URL
+    Direct { url: String },
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct RawKeySet {
+    #[serde(default)]
+    keys: Vec<Value>,
+}
+
+#[derive(Clone)]
+struct CachedKey {
+    key: DecodingKey,
+    alg: Option<Algorithm>,
+}
+
+#[derive(Default)]
+struct CachedKeys {
+    jwks_uri: Option<String>,
+    keys: HashMap<String, CachedKey>,
+    fetched_at: Option<Instant>,
+}
+
+/// JWKS fetched on first use and refreshed when it gets old or when a token
+/// names a `kid` we haven't seen (key rotation). Unknown-`kid` refreshes are
+/// throttled by `min_refresh` so garbage tokens can't hammer the IdP.
+pub struct JwksCache {
+    client: ReqwestClient,
+    source: JwksSource,
+    max_age: Duration,
+    min_refresh: Duration,
+    state: RwLock<CachedKeys>,
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl JwksCache {
+    pub fn new(client: ReqwestClient, source: JwksSource) -> Self {
+        Self {
+            client,
+            source,
+            max_age: Duration::from_secs(3600),
+            min_refresh: Duration::from_secs(10),
+            state: RwLock::new(CachedKeys::default()),
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn with_min_refresh(mut self, min_refresh: Duration) -> Self {
+        self.min_refresh = min_refresh;
+        self
+    }
+
+    /// Verify `token`'s signature against the cached keys and run the
+    /// registered-claim checks in `validation`. Returns the token's claims.
+    pub async fn verify(
+        &self,
+        token: &str,
+        allowed: &[Algorithm],
+        validation: &Validation,
+    ) -> Result<Map<String, Value>, JwtError> {
+        let header = decode_header(token).map_err(|_| JwtError::Malformed)?;
+        if !allowed.contains(&header.alg) {
+            return Err(JwtError::AlgorithmNotAllowed(header.alg));
+        }
+        let kid = header.kid.clone().unwrap_or_default();
+        let cached = self.key(&kid).await?;
+        if let Some(alg) = cached.alg {
+            if alg != header.alg {
+                return Err(JwtError::AlgorithmNotAllowed(header.alg));
+            }
+        }
+        let mut validation = validation.clone();
+        validation.algorithms = vec![header.alg];
+        let data = decode::<Map<String, Value>>(token, &cached.key, &validation)?;
+        Ok(data.claims)
+    }
+
+    async fn key(&self, kid: &str) -> Result<CachedKey, JwtError> {
+        if let Some(k) = self.lookup(kid, false) {
+            return Ok(k);
+        }
+        let _guard = self.refresh_lock.lock().await;
+        // Another request may have refreshed while we waited.
+        if let Some(k) = self.lookup(kid, false) {
+            return Ok(k);
+        }
+        let recently = self
+            .state
+            .read()
+            .fetched_at
+            .map(|t| t.elapsed() < self.min_refresh)
+            .unwrap_or(false);
+        if !recently {
+            if let Err(e) = self.refresh().await {
+                // Keep serving with the keys we have while the IdP is down.
+                warn!("JWKS refresh failed: {}", e);
+                return self.lookup(kid, true).ok_or(e);
+            }
+        }
+        self.lookup(kid, true)
+            .ok_or_else(|| JwtError::UnknownKey(kid.to_string()))
+    }
+
+    /// Find a key by id. Fresh-only lookups treat a stale cache as a miss so
+    /// the caller refreshes; after a refresh attempt any cached key is used.
+    fn lookup(&self, kid: &str, allow_stale: bool) -> Option<CachedKey> {
+        let state = self.state.read();
+        let fresh = state
+            .fetched_at
+            .map(|t| t.elapsed() < self.max_age)
+            .unwrap_or(false);
+        if !fresh && !allow_stale {
+            return None;
+        }
+        if kid.is_empty() && state.keys.len() == 1 {
+            return state.keys.values().next().cloned();
+        }
+        state.keys.get(kid).cloned()
+    }
+
+    async fn refresh(&self) -> Result<(), JwtError> {
+        let jwks_uri = match &self.source {
+            JwksSource::Direct { url } => url.clone(),
+            JwksSource::Discovery { issuer } => {
+                let known = self.state.read().jwks_uri.clone();
+                match known {
+                    Some(u) => u,
+                    None => {
+                        let url = format!(
+                            "{}/.well-known/openid-configuration",
+                            issuer.trim_end_matches('/')
+                        );
+                        let doc: DiscoveryDocument = self.fetch_json(&url).await?;
+                        self.state.write().jwks_uri = Some(doc.jwks_uri.clone());
+                        doc.jwks_uri
+                    }
+                }
+            }
+        };
+        let set: RawKeySet = self.fetch_json(&jwks_uri).await?;
+        let mut keys = HashMap::new();
+        for raw in set.keys {
+            // Skip keys we can't use instead of failing the whole set.
+            let Ok(jwk) = serde_json::from_value::<Jwk>(raw) else {
+                continue;
+            };
+            let Ok(key) = DecodingKey::from_jwk(&jwk) else {
+                continue;
+            };
+            let alg = jwk
+                .common
+                .key_algorithm
+                .and_then(|a| Algorithm::from_str(&a.to_string()).ok());
+            let kid = jwk.common.key_id.clone().unwrap_or_default();
+            keys.insert(kid, CachedKey { key, alg });
+        }
+        debug!("loaded {} signing keys from {}", keys.len(), jwks_uri);
+        let mut state = self.state.write();
+        state.keys = keys;
+        state.fetched_at = Some(Instant::now());
+        Ok(())
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, JwtError> {
+        let res = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| JwtError::Discovery(format!("{}: {}", url, e)))?;
+        if !res.status().is_success() {
+            return Err(JwtError::Discovery(format!(
+                "{}: HTTP {}",
+                url,
+                res.status()
+            )));
+        }
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| JwtError::Discovery(format!("{}: {}", url, e)))?;
+        serde_json::from_slice(&body).map_err(|e| JwtError::Discovery(format!("{}: {}", url, e)))
+    }
+}
+
+/// Read a claim from a JWT payload without verifying it. Only used to decide
+/// which provider should verify the token.
+pub fn peek_claim(token: &str, claim: &str) -> Option<String> {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    let payload = token.split('.').nth(1)?;
+    let bytes = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let v: Value = serde_json::from_slice(&bytes).ok()?;
+    v.get(claim)?.as_str().map(str::to_string)
+}
+
+/// Cheap shape check so opaque API keys aren't run through JWT parsing.
+pub fn looks_like_jwt(token: &str) -> bool {
+    token.split('.').count() == 3 && token.starts_with("ey")
+}
@@ -0,0 +1,363 @@
+// src/key_store.rs
+
+use crate::config::{ImagePolicy, ProxyConfig, SamplingPolicy, ToolPolicy};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum KeyStoreError {
+    #[error("failed to read key store '{path}': {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("failed to parse key store '{path}': {source}")]
+    Parse {
+        path: String,
+        source: serde_yaml::Error,
+    },
+    #[error("key '{0}': {1}")]
+    Invalid(String, String),
+    #[error("duplicate key id '{0}'")]
+    DuplicateId(String),
+}
+
+/// One tenant API key. Exactly one of `key` (the secret itself) or
+/// `key_sha256` (hex digest of the secret) must be set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub owner: String,
+    #[serde(default)]
+    pub key: Option<String>,
+    #[serde(default)]
+    pub key_sha256: Option<String>,
+    /// RFC 3339 timestamp (`Z` or a `+HH:MM` offset) or `YYYY-MM-DD`, taken
+    /// as midnight UTC; the key is rejected from then on
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    // Per-key overrides of the global `proxy` policy
+    #[serde(default)]
+    pub model_allowlist: Option<HashSet<String>>,
+    #[serde(default)]
+    pub endpoint_allowlist: Option<Vec<String>>,
+    #[serde(default)]
+    pub endpoint_denylist: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_prompt_chars: Option<usize>,
+    #[serde(default)]
+    pub max_num_ctx: Option<u64>,
+    #[serde(default)]
+    pub max_num_predict: Option<i64>,
+    #[serde(default)]
+    pub sampling: Option<SamplingPolicy>,
+    #[serde(default)]
+    pub images: Option<ImagePolicy>,
+    #[serde(default)]
+    pub tools: Option<ToolPolicy>,
+
+    /// Priority class for admission control
+    #[serde(default)]
+    pub priority: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyFile {
+    #[serde(default)]
+    keys: Vec<ApiKey>,
+}
+
+impl ApiKey {
+    /// The global policy with this key's overrides applied.
+    pub fn effective_policy(&self, global: &ProxyConfig) -> ProxyConfig {
+        let mut p = global.clone();
+        if let Some(v) = &self.model_allowlist {
+            p.model_allowlist = v.clone();
+        }
+        if let Some(v) = &self.endpoint_allowlist {
+            p.endpoint_allowlist = v.clone();
+        }
+        if let Some(v) = &self.endpoint_denylist {
+            p.endpoint_denylist = v.clone();
+        }
+        if let Some(v) = self.max_prompt_chars {
+            p.max_prompt_chars = v;
+        }
+        if let Some(v) = self.max_num_ctx {
+            p.max_num_ctx = v;
+        }
+        if let Some(v) = self.max_num_predict {
+            p.max_num_predict = v;
+        }
+        if let Some(v) = &self.sampling {
+            p.sampling = v.clone();
+        }
+        if let Some(v) = &self.images {
+            p.images = v.clone();
+        }
+        if let Some(v) = &self.tools {
+            p.tools = v.clone();
+        }
+        p
+    }
+
+    fn expiry(&self) -> Result<Option<SystemTime>, KeyStoreError> {
+        let Some(raw) = &self.expires_at else {
+            return Ok(None);
+        };
+        parse_expiry(raw)
+            .map(Some)
+            .map_err(|e| KeyStoreError::Invalid(self.id.clone(), format!("expires_at: {}", e)))
+    }
+}
+
+/// A date, or an RFC 3339 timestamp in UTC (`Z` or no zone) or with a
+/// `±HH:MM` offset.
+fn parse_expiry(raw: &str) -> Result<SystemTime, String> {
+    if raw.len() == 10 {
+        return humantime::parse_rfc3339_weak(&format!("{}T00:00:00", raw))
+            .map_err(|e| e.to_string());
+    }
+    let zone = raw
+        .len()
+        .checked_sub(6)
+        .filter(|&i| raw.is_char_boundary(i) && matches!(raw.as_bytes()[i], b'+' | b'-'));
+    let (local, offset) = match zone {
+        Some(i) => (&raw[..i], parse_offset(&raw[i..])?),
+        None => (raw.trim_end_matches(['Z', 'z']), 0),
+    };
+    let local = humantime::parse_rfc3339_weak(local).map_err(|e| e.to_string())?;
+    let shift = Duration::from_secs(offset.unsigned_abs());
+    Ok(if offset >= 0 {
+        local - shift
+    } else {
+        local + shift
+    })
+}
+
+/// `+HH:MM` or `-HH:MM` in seconds east of UTC.
+fn parse_offset(zone: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid offset '{}'", zone);
+    let (hours, minutes) = zone[1..].split_once(':').ok_or_else(invalid)?;
+    let field = |s: &str, max: i64| {
+        s.parse::<i64>()
+            .ok()
+            .filter(|n| s.len() == 2 && *n <= max)
+            .ok_or_else(invalid)
+    };
+    let secs = field(hours, 23)? * 3600 + field(minutes, 59)? * 60;
+    Ok(if zone.starts_with('-') { -secs } else { secs })
+}
+
+/// Why a presented key was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRejection {
+    Disabled,
+    Expired,
+}
+
+struct StoredKey {
+    key: Arc<ApiKey>,
+    expires: Option<SystemTime>,
+}
+
+/// API keys loaded from a YAML/JSON file or from every `.yaml`, `.yml` and
+/// `.json` file in a directory. Keys are indexed by the SHA-256 of their
+/// secret so plaintext secrets needn't be kept on disk.
+#[derive(Default)]
+pub struct KeyStore {
+    by_hash: HashMap<String, StoredKey>,
+}
+
+impl KeyStore {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, KeyStoreError> {
+        let path = path.as_ref();
+        let io_err = |source| KeyStoreError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+        let mut files = Vec::new();
+        if path.is_dir() {
+            for entry in fs::read_dir(path).map_err(io_err)? {
+                let p = entry.map_err(io_err)?.path();
+                let ext = p.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if matches!(ext, "yaml" | "yml" | "json") {
+                    files.push(p);
+                }
+            }
+            files.sort();
+        } else {
+            files.push(path.to_path_buf());
+        }
+
+        let mut keys = Vec::new();
+        for file in files {
+            let text = fs::read_to_string(&file).map_err(|source| KeyStoreError::Io {
+                path: file.display().to_string(),
+                source,
+            })?;
+            let parsed: KeyFile =
+                serde_yaml::from_str(&text).map_err(|source| KeyStoreError::Parse {
+                    path: file.display().to_string(),
+                    source,
+                })?;
+            keys.extend(parsed.keys);
+        }
+        Self::from_keys(keys)
+    }
+
+    pub fn from_keys(keys: Vec<ApiKey>) -> Result<Self, KeyStoreError> {
+        let mut ids = HashSet::new();
+        let mut by_hash: HashMap<String, StoredKey> = HashMap::new();
+        for k in keys {
+            if !ids.insert(k.id.clone()) {
+                return Err(KeyStoreError::DuplicateId(k.id));
+            }
+            let hash = match (&k.key, &k.key_sha256) {
+                (Some(secret), None) => sha256_hex(secret),
+                (None, Some(h)) => h.to_ascii_lowercase(),
+                _ => {
+                    return Err(KeyStoreError::Invalid(
+                        k.id.clone(),
+                        "set exactly one of key or key_sha256".into(),
+                    ))
+                }
+            };
+            if let Some(other) = by_hash.get(&hash) {
+                return Err(KeyStoreError::Invalid(
+                    k.id.clone(),
+                    format!("same secret as key '{}'", other.key.id),
+                ));
+            }
+            let expires = k.expiry()?;
+            by_hash.insert(
+                hash,
+                StoredKey {
+                    key: Arc::new(k),
+                    expires,
+                },
+            );
+        }
+        Ok(Self { by_hash })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    /// Resolve a presented secret. `None` if no key matches.
+    pub fn lookup(&self, secret: &str) -> Option<Result<Arc<ApiKey>, KeyRejection>> {
+        let stored = self.by_hash.get(&sha256_hex(secret))?;
+        if !stored.key.enabled {
+            return Some(Err(KeyRejection::Disabled));
+        }
+        if stored.expires.is_some_and(|t| t <= SystemTime::now()) {
+            return Some(Err(KeyRejection::Expired));
+        }
+        Some(Ok(stored.key.clone()))
+    }
+}
+
+pub fn sha256_hex(s: &str) -> String {
+    hex::encode(Sha256::digest(s.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_lookup_and_overrides() {
+        let dir = std::env::temp_dir().join(format!("gamb-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("team-a.yaml"),
+            r#"
+keys:
+  - id: a1
+    owner: team-a
+    key: secret-a1
+    model_allowlist: ["llama3:8b"]
+    max_num_predict: 256
+  - id: a2
+    owner: team-a
+    key: secret-a2
+    enabled: false
+"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("team-b.json"),
+            format!(
+                r#"{{"keys":[{{"id":"b1","owner":"team-b","key_sha256":"{}","expires_at":"2001-01-01"}}]}}"#,
+                sha256_hex("secret-b1")
+            ),
+        )
+        .unwrap();
+        fs::write(dir.join("README.txt"), "ignored").unwrap();
+
+        let store = KeyStore::load(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(store.len(), 3);
+
+        let a1 = store.lookup("secret-a1").unwrap().unwrap();
+        assert_eq!(a1.owner, "team-a");
+        let policy = a1.effective_policy(&ProxyConfig::default());
+        assert!(policy.model_allowlist.contains("llama3:8b"));
+        assert_eq!(policy.max_num_predict, 256);
+        assert_eq!(
+            policy.max_prompt_chars,
+            ProxyConfig::default().max_prompt_chars
+        );
+
+        assert_eq!(
+            store.lookup("secret-a2").unwrap().unwrap_err(),
+            KeyRejection::Disabled
+        );
+        assert_eq!(
+            store.lookup("secret-b1").unwrap().unwrap_err(),
+            KeyRejection::Expired
+        );
+        assert!(store.lookup("nope").is_none());
+    }
+
+    #[test]
+    fn test_rejects_shared_secrets_and_reads_offsets() {
+        let key = |id: &str, secret: &str, expires: Option<&str>| ApiKey {
+            expires_at: expires.map(str::to_string),
+            ..serde_yaml::from_str(&format!("{{ id: {}, owner: o, key: {} }}", id, secret))
+                .unwrap()
+        };
+        let err = KeyStore::from_keys(vec![key("a", "s", None), key("b", "s", None)]);
+        assert!(matches!(err, Err(KeyStoreError::Invalid(id, _)) if id == "b"));
+
+        let utc = parse_expiry("2030-06-01T12:00:00Z").unwrap();
+        assert_eq!(parse_expiry("2030-06-01T14:00:00+02:00").unwrap(), utc);
+        assert_eq!(parse_expiry("2030-06-01T07:30:00-04:30").unwrap(), utc);
+        assert_eq!(
+            parse_expiry("2030-06-01").unwrap() + Duration::from_secs(12 * 3600),
+            utc
+        );
+        assert!(parse_expiry("2030-06-01T12:00:00+25:00").is_err());
+        assert!(KeyStore::from_keys(vec![key("c", "t", Some("soon"))]).is_err());
+    }
+}
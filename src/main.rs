@@ -3,18 +3,53 @@
 pub mod echo {
     tonic::include_proto!("echo");
 }
+mod admission;
+mod audit;
+mod auth;
 mod backend_registry;
+mod cache;
+mod cf_access;
 mod config;
 mod consul_integration;
+mod content_rules;
+mod dlp;
 mod grpc_service;
+mod health;
 mod http_proxy;
+mod images;
+mod jwks;
+mod key_store;
+mod metrics;
 mod middleware;
+mod models;
+mod oidc;
+mod openai;
+mod quota;
+mod rate_limit;
+mod reload;
+mod response_stream;
+mod retry;
+mod router;
+mod sampling;
 mod tcp_udp_proxy;
 mod tls_config;
+mod tools;
+mod usage;
+mod validate;
 
-use backend_registry::BackendRegistry;
-use config::Config;
+use admission::Admission;
+use audit::AuditLog;
+use backend_registry::{BackendRegistry, OutlierPolicy};
+use cache::ResponseCache;
+use config::{Config, ConfigError};
+use content_rules::ContentRules;
+use dlp::DlpScanner;
+use health::HealthChecker;
+use http_proxy::GatewayState;
+use key_store::KeyStore;
 use log::{error, info};
+use reload::Reloader;
+use retry::RetryPolicy;
 use std::{error::Error, net::SocketAddr, sync::Arc, time::Duration};
 use tls_config::TlsConfig;
 use tokio::spawn;
@@ -23,6 +58,10 @@ use tokio::spawn;
 async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
     let config_path = std::env::var("GATEWAY_CONFIG").unwrap_or_else(|_| "config.yaml".to_string());
+    if std::env::args().nth(1).as_deref() == Some("check-config") {
+        let path = std::env::args().nth(2).unwrap_or(config_path);
+        std::process::exit(check_config(&path));
+    }
     let cfg = Config::from_file(&config_path).map_err(|e| {
         error!("Config load failed: {}", e);
         e
@@ -37,68 +76,111 @@ async fn main() -> Result<(), Box<dyn Error>> {
     for be in &cfg.backends {
         registry.register(&be.name, &be.address);
     }
+    if let Some(od) = &cfg.outlier_detection {
+        let policy = OutlierPolicy::new(od).map_err(|e| {
+            error!("Outlier detection config invalid: {}", e);
+            e
+        })?;
+        registry.set_outlier_policy(Some(policy));
+    }
+    let health = HealthChecker::new(&cfg.backends, registry.clone()).map_err(|e| {
+        error!("Health check config invalid: {}", e);
+        e
+    })?;
+    info!("Health checking {} backends", health.len());
+    let health_tasks = health.spawn();
 
-    let bearer = cfg.bearer_token.clone();
-    let rate_limit = cfg.rate_limit_per_sec as u64;
-    let rate_period = Duration::from_secs(1);
-    let https_port = cfg.https_port.unwrap_or(cfg.http_port + 1);
+    let keys = match &cfg.key_store {
+        Some(path) => KeyStore::load(path).map_err(|e| {
+            error!("Key store load failed: {}", e);
+            e
+        })?,
+        None => KeyStore::default(),
+    };
+    info!("Loaded {} API keys", keys.len());
+    let audit = match &cfg.audit {
+        Some(a) => {
+            info!("Writing audit log to {}", a.path);
+            Some(AuditLog::open(a).map_err(|e| {
+                error!("Audit log open failed: {}", e);
+                e
+            })?)
+        }
+        None => None,
+    };
+    let dlp = DlpScanner::new(&cfg.dlp).map_err(|e| {
+        error!("DLP config invalid: {}", e);
+        e
+    })?;
+    let rules = ContentRules::new(&cfg.content_rules).map_err(|e| {
+        error!("Content rules invalid: {}", e);
+        e
+    })?;
+    let cache = match &cfg.cache {
+        Some(c) => Some(ResponseCache::new(c).map_err(|e| {
+            error!("Response cache config invalid: {}", e);
+            e
+        })?),
+        None => None,
+    };
+    let admission = match &cfg.concurrency {
+        Some(c) => Some(Admission::new(c).map_err(|e| {
+            error!("Concurrency config invalid: {}", e);
+            e
+        })?),
+        None => None,
+    };
+    let retry = match &cfg.retry {
+        Some(r) => Some(RetryPolicy::new(r).map_err(|e| {
+            error!("Retry config invalid: {}", e);
+            e
+        })?),
+        None => None,
+    };
+    let gateway = Arc::new(
+        GatewayState::new(&cfg, registry.clone(), keys)
+            .with_audit(audit)
+            .with_dlp(dlp)
+            .with_content_rules(rules)
+            .with_cache(cache)
+            .with_admission(admission)
+            .with_retry(retry),
+    );
+    let reloader = Reloader::new(
+        &config_path,
+        &cfg,
+        gateway.clone(),
+        registry.clone(),
+        health_tasks,
+    )
+    .map_err(|e| {
+        error!("Reload config invalid: {}", e);
+        e
+    })?;
+    spawn(reloader.run());
 
+    // Validated with the rest of the config, so these do not fail.
+    let https_addr = cfg.https_addr()?;
+    let http_addr = cfg.http_addr()?;
+    let grpc_addr = cfg.grpc_addr()?;
     {
-        let reg = registry.clone();
+        let state = gateway.clone();
         let acceptor = tls_acceptor.clone();
-        let auth = bearer.clone();
-        let auth_cfg = cfg.auth.clone();
-        let proxy = cfg.proxy.clone();
-        let bind = cfg
-            .https_bind_addr
-            .clone()
-            .unwrap_or_else(|| "127.0.0.1".to_string());
         spawn(async move {
-            let addr: SocketAddr = format!("{}:{}", bind, https_port)
-                .parse()
-                .expect("invalid HTTPS addr");
-            http_proxy::run_https_gateway(
-                addr,
-                reg,
-                acceptor,
-                auth,
-                auth_cfg,
-                proxy,
-                rate_limit,
-                rate_period,
-            )
-            .await;
+            http_proxy::run_https_gateway(https_addr, state, acceptor).await;
         });
     }
     {
-        let reg = registry.clone();
-        let auth = bearer.clone();
-        let auth_cfg = cfg.auth.clone();
-        let proxy = cfg.proxy.clone();
-        let http_port = cfg.http_port;
-        let bind = cfg
-            .http_bind_addr
-            .clone()
-            .unwrap_or_else(|| "127.0.0.1".to_string());
+        let state = gateway.clone();
         spawn(async move {
-            let addr: SocketAddr = format!("{}:{}", bind, http_port)
-                .parse()
-                .expect("invalid HTTP addr");
-            http_proxy::run_http_gateway(addr, reg, auth, auth_cfg, proxy, rate_limit, rate_period)
-                .await;
+            http_proxy::run_http_gateway(http_addr, state).await;
         });
     }
 
     {
         let reg = registry.clone();
-        let port = cfg.grpc_port.unwrap_or(50051);
-        let bind = cfg
-            .grpc_bind_addr
-            .clone()
-            .unwrap_or_else(|| "127.0.0.1".to_string());
         spawn(async move {
-            let bind = format!("{}:{}", bind, port);
-            grpc_service::run_grpc_gateway(&bind, reg)
+            grpc_service::run_grpc_gateway(&grpc_addr.to_string(), reg)
                 .await
                 .unwrap_or_else(|e| error!("gRPC gateway failed: {}", e));
         });
@@ -108,3 +190,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
         tokio::time::sleep(Duration::from_secs(3600)).await;
     }
 }
+
+/// `gamb check-config <file>`: print every problem found in the file and
+/// return the exit code.
+fn check_config(path: &str) -> i32 {
+    match Config::from_file(path) {
+        Ok(_) => {
+            println!("{}: OK", path);
+            0
+        }
+        Err(ConfigError::Invalid(issues)) => {
+            for issue in &issues {
+                eprintln!("{}: {}", path, issue);
+            }
+            1
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
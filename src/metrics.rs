@@ -0,0 +1,344 @@
+// src/metrics.rs
+
+use crate::backend_registry::{BackendRegistry, Circuit};
+use prometheus_client::{
+    encoding::{text::encode, EncodeLabelSet},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Endpoints reported by name in the `route` label. Anything else is
+/// reported as the matched backend route prefix, or `other`, to keep label
+/// cardinality bounded.
+const KNOWN_ROUTES: &[&str] = &[
+    "/api/generate",
+    "/api/chat",
+    "/api/embed",
+    "/api/embeddings",
+    "/api/tags",
+    "/api/show",
+    "/api/ps",
+    "/api/version",
+    "/api/pull",
+    "/api/push",
+    "/api/create",
+    "/api/copy",
+    "/api/delete",
+    "/v1/chat/completions",
+    "/v1/completions",
+    "/v1/embeddings",
+    "/v1/models",
+];
+
+pub fn route_label(path: &str, matched_prefix: Option<&str>) -> String {
+    if KNOWN_ROUTES.contains(&path) {
+        return path.to_string();
+    }
+    matched_prefix.unwrap_or("other").to_string()
+}
+
+/// Model names come from client input, so only `known` ones, named by the
+/// config, get their own label; the rest share `other`.
+pub fn model_label(model: Option<&str>, known: bool) -> String {
+    match model {
+        Some(m) if known => m.to_string(),
+        Some(_) => "other".to_string(),
+        None => String::new(),
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RequestLabels {
+    pub listener: String,
+    pub method: String,
+    pub route: String,
+    pub model: String,
+    pub status: u16,
+    pub tenant: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct LatencyLabels {
+    pub listener: String,
+    pub route: String,
+    pub model: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct UpstreamErrorLabels {
+    pub upstream: String,
+    pub kind: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ReasonLabels {
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct ListenerLabels {
+    pub listener: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct RouteClassLabels {
+    pub class: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct CacheLabels {
+    pub result: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct BackendLabels {
+    pub service: String,
+    pub backend: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct QueueLabels {
+    pub model: String,
+    pub class: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct PriorityLabels {
+    pub class: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct QueueWaitLabels {
+    pub model: String,
+    pub class: String,
+    /// `admitted` or `timeout`
+    pub result: String,
+}
+
+type HistogramFamily<L> = Family<L, Histogram, fn() -> Histogram>;
+
+fn latency_histogram() -> Histogram {
+    // 5ms .. ~5.5min
+    Histogram::new(exponential_buckets(0.005, 2.0, 17))
+}
+
+fn position_histogram() -> Histogram {
+    // 0 .. 512 requests ahead
+    Histogram::new([0.0].into_iter().chain(exponential_buckets(1.0, 2.0, 10)))
+}
+
+/// Gateway-wide metrics shared by every listener and served at `/metrics`
+/// in OpenMetrics text format.
+pub struct GatewayMetrics {
+    registry: Registry,
+    pub requests: Family<RequestLabels, Counter>,
+    pub request_duration: HistogramFamily<LatencyLabels>,
+    pub upstream_duration: HistogramFamily<LatencyLabels>,
+    pub upstream_errors: Family<UpstreamErrorLabels, Counter>,
+    pub policy_rejections: Family<ReasonLabels, Counter>,
+    pub rate_limited: Family<RouteClassLabels, Counter>,
+    pub in_flight: Family<ListenerLabels, Gauge>,
+    pub active_streams: Family<ListenerLabels, Gauge>,
+    pub cache: Family<CacheLabels, Counter>,
+    pub queue_depth: Family<QueueLabels, Gauge>,
+    pub queue_wait: HistogramFamily<QueueWaitLabels>,
+    pub queue_position: HistogramFamily<PriorityLabels>,
+    pub backend_healthy: Family<BackendLabels, Gauge>,
+    pub backend_ejected: Family<BackendLabels, Gauge>,
+    pub upstream_retries: Family<ReasonLabels, Counter>,
+}
+
+impl Default for GatewayMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GatewayMetrics {
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("gamb");
+        let requests = Family::<RequestLabels, Counter>::default();
+        registry.register(
+            "http_requests",
+            "HTTP requests by listener, method, route, model, status and tenant",
+            requests.clone(),
+        );
+        let request_duration: HistogramFamily<LatencyLabels> =
+            Family::new_with_constructor(latency_histogram);
+        registry.register(
+            "http_request_duration_seconds",
+            "Time from request arrival until the response body finished",
+            request_duration.clone(),
+        );
+        let upstream_duration: HistogramFamily<LatencyLabels> =
+            Family::new_with_constructor(latency_histogram);
+        registry.register(
+            "upstream_response_seconds",
+            "Time until the upstream returned response headers",
+            upstream_duration.clone(),
+        );
+        let upstream_errors = Family::<UpstreamErrorLabels, Counter>::default();
+        registry.register(
+            "upstream_errors",
+            "Failed upstream requests by kind (connect, timeout, body, other)",
+            upstream_errors.clone(),
+        );
+        let policy_rejections = Family::<ReasonLabels, Counter>::default();
+        registry.register(
+            "policy_rejections",
+            "Requests refused by gateway policy, by reason",
+            policy_rejections.clone(),
+        );
+        let rate_limited = Family::<RouteClassLabels, Counter>::default();
+        registry.register(
+            "rate_limited",
+            "Requests rejected by the rate limiter, by route class",
+            rate_limited.clone(),
+        );
+        let in_flight = Family::<ListenerLabels, Gauge>::default();
+        registry.register(
+            "http_requests_in_flight",
+            "Requests currently being served, including streaming bodies",
+            in_flight.clone(),
+        );
+        let active_streams = Family::<ListenerLabels, Gauge>::default();
+        registry.register(
+            "active_streams",
+            "Upstream response bodies currently streaming to clients",
+            active_streams.clone(),
+        );
+        let cache = Family::<CacheLabels, Counter>::default();
+        registry.register(
+            "cache_requests",
+            "Response cache lookups by result (hit, miss, bypass)",
+            cache.clone(),
+        );
+        let queue_depth = Family::<QueueLabels, Gauge>::default();
+        registry.register(
+            "admission_queue_depth",
+            "Requests waiting for a concurrency slot, by model and priority class",
+            queue_depth.clone(),
+        );
+        let queue_wait: HistogramFamily<QueueWaitLabels> =
+            Family::new_with_constructor(latency_histogram);
+        registry.register(
+            "admission_wait_seconds",
+            "Time queued requests waited for a concurrency slot",
+            queue_wait.clone(),
+        );
+        let queue_position: HistogramFamily<PriorityLabels> =
+            Family::new_with_constructor(position_histogram);
+        registry.register(
+            "admission_queue_position",
+            "Requests ahead of a queued request when it joined the queue",
+            queue_position.clone(),
+        );
+        let backend_healthy = Family::<BackendLabels, Gauge>::default();
+        registry.register(
+            "backend_healthy",
+            "1 if the backend passes its health checks, else 0",
+            backend_healthy.clone(),
+        );
+        let backend_ejected = Family::<BackendLabels, Gauge>::default();
+        registry.register(
+            "backend_ejected",
+            "1 while outlier detection keeps the backend out of rotation, else 0",
+            backend_ejected.clone(),
+        );
+        let upstream_retries = Family::<ReasonLabels, Counter>::default();
+        registry.register(
+            "upstream_retries",
+            "Upstream requests retried, by reason; budget_exhausted counts retries refused",
+            upstream_retries.clone(),
+        );
+        Self {
+            registry,
+            requests,
+            request_duration,
+            upstream_duration,
+            upstream_errors,
+            policy_rejections,
+            rate_limited,
+            in_flight,
+            active_streams,
+            cache,
+            queue_depth,
+            queue_wait,
+            queue_position,
+            backend_healthy,
+            backend_ejected,
+            upstream_retries,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        encode(&mut out, &self.registry).expect("encoding metrics into a String");
+        out
+    }
+
+    pub fn cache_result(&self, result: &str) {
+        self.cache
+            .get_or_create(&CacheLabels {
+                result: result.to_string(),
+            })
+            .inc();
+    }
+
+    /// Refresh the backend health and ejection gauges from the registry.
+    pub fn backend_health(&self, registry: &BackendRegistry) {
+        for (service, entries) in registry.snapshot() {
+            for e in entries {
+                let labels = BackendLabels {
+                    service: service.clone(),
+                    backend: e.url,
+                };
+                self.backend_healthy
+                    .get_or_create(&labels)
+                    .set(e.healthy as i64);
+                self.backend_ejected
+                    .get_or_create(&labels)
+                    .set((e.circuit == Circuit::Open) as i64);
+            }
+        }
+    }
+
+    pub fn retry(&self, reason: &str) {
+        self.upstream_retries
+            .get_or_create(&ReasonLabels {
+                reason: reason.to_string(),
+            })
+            .inc();
+    }
+
+    pub fn reject(&self, reason: &str) {
+        self.policy_rejections
+            .get_or_create(&ReasonLabels {
+                reason: reason.to_string(),
+            })
+            .inc();
+    }
+}
+
+/// Holds a gauge incremented for as long as the guard lives.
+pub struct GaugeGuard(Gauge);
+
+impl GaugeGuard {
+    pub fn new(gauge: Gauge) -> Self {
+        gauge.inc();
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
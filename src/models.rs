@@ -0,0 +1,389 @@
+// src/models.rs
+
+use crate::{config::ModelsConfig, response_stream::BodyRewrite};
+use bytes::Bytes;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+
+/// Endpoints whose request `model` (or `name`) may be an alias.
+const ALIASED_PATHS: &[&str] = &[
+    "/api/generate",
+    "/api/chat",
+    "/api/embed",
+    "/api/embeddings",
+    "/api/show",
+    "/v1/chat/completions",
+    "/v1/completions",
+    "/v1/embeddings",
+];
+
+/// `name` refers to `tag`, allowing for Ollama's implicit `:latest`.
+fn same_model(name: &str, tag: &str) -> bool {
+    let base = |s: &str| s.strip_suffix(":latest").unwrap_or(s).to_string();
+    base(name) == base(tag)
+}
+
+/// Shell-style match: `*` is any run of characters, `?` any one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    let mut star = None;
+    while ni < n.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi, ni));
+                pi += 1;
+            }
+            Some(c) if *c == '?' || *c == n[ni] => {
+                pi += 1;
+                ni += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character.
+                Some((sp, sn)) => {
+                    pi = sp + 1;
+                    ni = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+/// Maps model names to the backend service whose pool serves them.
+#[derive(Default)]
+pub struct ModelRouter {
+    routes: Vec<(String, String)>,
+}
+
+impl ModelRouter {
+    pub fn new(cfg: &ModelsConfig) -> Self {
+        Self {
+            routes: cfg
+                .routes
+                .iter()
+                .flat_map(|r| r.models.iter().map(|m| (m.clone(), r.service.clone())))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// The service for `model`, trying routes in order. A pattern without
+    /// a tag also matches `<pattern>:latest`.
+    pub fn service_for(&self, model: &str) -> Option<&str> {
+        let bare = model.strip_suffix(":latest").unwrap_or(model);
+        self.routes
+            .iter()
+            .find(|(p, _)| glob_match(p, model) || glob_match(p, bare))
+            .map(|(_, s)| s.as_str())
+    }
+}
+
+/// Stable client-facing model names mapped to concrete upstream tags.
+#[derive(Default)]
+pub struct ModelAliases {
+    aliases: BTreeMap<String, String>,
+    hide_unaliased: bool,
+}
+
+impl ModelAliases {
+    pub fn new(cfg: &ModelsConfig) -> Self {
+        Self {
+            aliases: cfg
+                .aliases
+                .iter()
+                .map(|(a, t)| (a.clone(), t.clone()))
+                .collect(),
+            hide_unaliased: cfg.hide_unaliased,
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> Option<&str> {
+        self.aliases.get(name).map(String::as_str)
+    }
+
+    /// Replace an aliased `model` or `name` in a request body. Returns the
+    /// alias and the rewritten body.
+    pub fn rewrite_request(&self, path: &str, body: &[u8]) -> Option<(String, Bytes)> {
+        if self.aliases.is_empty() || !ALIASED_PATHS.contains(&path) || !body.starts_with(b"{") {
+            return None;
+        }
+        let mut doc: Value = serde_json::from_slice(body).ok()?;
+        let obj = doc.as_object_mut()?;
+        let mut alias = None;
+        for key in ["model", "name"] {
+            let Some(Value::String(name)) = obj.get_mut(key) else {
+                continue;
+            };
+            if let Some(target) = self.aliases.get(name.as_str()) {
+                alias = Some(std::mem::replace(name, target.clone()));
+            }
+        }
+        let alias = alias?;
+        Some((alias, Bytes::from(serde_json::to_vec(&doc).ok()?)))
+    }
+
+    /// Rewrites the concrete tag back to `alias` in a response.
+    pub fn response_rewrite(&self, alias: &str) -> Option<NameRewrite> {
+        let target = self.resolve(alias)?;
+        let replace = |name: &str| {
+            [":", ": "].map(|sep| {
+                (
+                    format!("\"model\"{}\"{}\"", sep, name),
+                    format!("\"model\"{}\"{}\"", sep, alias),
+                )
+            })
+        };
+        let mut pairs = replace(target).to_vec();
+        if !target.contains(':') {
+            pairs.extend(replace(&format!("{}:latest", target)));
+        }
+        Some(NameRewrite {
+            pairs,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Rewrites a model listing to show the aliases, limited to models the
+    /// caller may use.
+    pub fn listing_rewrite(&self, allowlist: &HashSet<String>) -> Option<ListingRewrite> {
+        (!self.aliases.is_empty() || !allowlist.is_empty()).then(|| ListingRewrite {
+            aliases: self.aliases.clone(),
+            hide_unaliased: self.hide_unaliased,
+            allowlist: allowlist.clone(),
+            buf: Vec::new(),
+        })
+    }
+}
+
+/// Replaces `"model":"<tag>"` with the alias, line by line.
+pub struct NameRewrite {
+    pairs: Vec<(String, String)>,
+    buf: Vec<u8>,
+}
+
+impl NameRewrite {
+    fn rewrite(&self, line: &[u8], out: &mut Vec<u8>) {
+        let Ok(text) = std::str::from_utf8(line) else {
+            out.extend_from_slice(line);
+            return;
+        };
+        if !self
+            .pairs
+            .iter()
+            .any(|(from, _)| text.contains(from.as_str()))
+        {
+            out.extend_from_slice(line);
+            return;
+        }
+        let mut text = text.to_string();
+        for (from, to) in &self.pairs {
+            text = text.replace(from.as_str(), to);
+        }
+        out.extend_from_slice(text.as_bytes());
+    }
+}
+
+impl BodyRewrite for NameRewrite {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.buf.extend_from_slice(chunk);
+        let mut out = Vec::new();
+        while let Some(i) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            self.rewrite(&line, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.buf);
+        let mut out = Vec::new();
+        self.rewrite(&rest, &mut out);
+        out
+    }
+}
+
+/// Adds alias entries to `/api/tags` (`models[].name`) or `/v1/models`
+/// (`data[].id`) and drops models that are not allowed.
+pub struct ListingRewrite {
+    aliases: BTreeMap<String, String>,
+    hide_unaliased: bool,
+    allowlist: HashSet<String>,
+    buf: Vec<u8>,
+}
+
+impl ListingRewrite {
+    fn rewrite(&self, doc: &mut Value) {
+        let (list, keys): (_, &[&str]) = match doc {
+            Value::Object(o) if o.get("models").is_some_and(Value::is_array) => {
+                (o.get_mut("models"), &["name", "model"])
+            }
+            Value::Object(o) => (o.get_mut("data"), &["id"]),
+            _ => return,
+        };
+        let Some(Value::Array(entries)) = list else {
+            return;
+        };
+        let name_of = |e: &Value| {
+            e.get(keys[0])
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let mut listed = Vec::new();
+        for (alias, target) in &self.aliases {
+            if let Some(e) = entries.iter().find(|e| same_model(&name_of(e), target)) {
+                let mut e = e.clone();
+                for k in keys {
+                    if e.get(*k).is_some() {
+                        e[*k] = Value::from(alias.as_str());
+                    }
+                }
+                listed.push((e, Some(target.as_str())));
+            }
+        }
+        if !self.hide_unaliased {
+            listed.extend(entries.drain(..).map(|e| (e, None)));
+        }
+        let allowed = |e: &Value, target: Option<&str>| {
+            self.allowlist.is_empty()
+                || self.allowlist.contains(&name_of(e))
+                || target.is_some_and(|t| self.allowlist.contains(t))
+        };
+        *entries = listed
+            .into_iter()
+            .filter(|(e, t)| allowed(e, *t))
+            .map(|(e, _)| e)
+            .collect();
+    }
+}
+
+impl BodyRewrite for ListingRewrite {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.buf.extend_from_slice(chunk);
+        Vec::new()
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let body = std::mem::take(&mut self.buf);
+        let Ok(mut doc) = serde_json::from_slice::<Value>(&body) else {
+            return body;
+        };
+        self.rewrite(&mut doc);
+        serde_json::to_vec(&doc).unwrap_or(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(hide_unaliased: bool) -> ModelAliases {
+        ModelAliases::new(&ModelsConfig {
+            aliases: [
+                ("chat-default".to_string(), "llama3.1".to_string()),
+                (
+                    "code-large".to_string(),
+                    "qwen2.5-coder:32b-instruct-q4_K_M".to_string(),
+                ),
+            ]
+            .into(),
+            hide_unaliased,
+            ..ModelsConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_rewrites_requests_and_responses() {
+        let a = aliases(false);
+        let (alias, body) = a
+            .rewrite_request("/api/chat", br#"{"model":"code-large","messages":[]}"#)
+            .unwrap();
+        assert_eq!(alias, "code-large");
+        let doc: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(doc["model"], "qwen2.5-coder:32b-instruct-q4_K_M");
+        assert!(a
+            .rewrite_request("/api/chat", br#"{"model":"llama3.1"}"#)
+            .is_none());
+        assert!(a
+            .rewrite_request("/api/delete", br#"{"model":"code-large"}"#)
+            .is_none());
+
+        let mut r = a.response_rewrite("chat-default").unwrap();
+        let mut out = r.feed(b"{\"model\":\"llama3.1:latest\",\"response\":\"a\"}\n{\"model\":");
+        out.extend(r.feed(b"\"llama3.1:latest\",\"done\":true}"));
+        out.extend(r.finish());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"model\":\"chat-default\",\"response\":\"a\"}\n{\"model\":\"chat-default\",\"done\":true}"
+        );
+    }
+
+    #[test]
+    fn test_listing_shows_allowed_aliases() {
+        let tags = br#"{"models":[
+            {"name":"llama3.1:latest","model":"llama3.1:latest","size":1},
+            {"name":"qwen2.5-coder:32b-instruct-q4_K_M","model":"qwen2.5-coder:32b-instruct-q4_K_M","size":2},
+            {"name":"mistral:latest","model":"mistral:latest","size":3}
+        ]}"#;
+        let names = |r: &mut ListingRewrite, body: &[u8], key: &str| -> Vec<String> {
+            r.feed(body);
+            let doc: Value = serde_json::from_slice(&r.finish()).unwrap();
+            let list = doc.get("models").or_else(|| doc.get("data")).unwrap();
+            list.as_array()
+                .unwrap()
+                .iter()
+                .map(|e| e[key].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let mut all = aliases(false).listing_rewrite(&HashSet::new()).unwrap();
+        assert_eq!(
+            names(&mut all, tags, "name"),
+            [
+                "chat-default",
+                "code-large",
+                "llama3.1:latest",
+                "qwen2.5-coder:32b-instruct-q4_K_M",
+                "mistral:latest"
+            ]
+        );
+
+        // Only aliases, and only those whose alias or target is allowed.
+        let allow: HashSet<String> = ["code-large".to_string()].into();
+        let mut only = aliases(true).listing_rewrite(&allow).unwrap();
+        assert_eq!(names(&mut only, tags, "name"), ["code-large"]);
+
+        let openai = br#"{"object":"list","data":[{"id":"llama3.1","object":"model"}]}"#;
+        let mut r = aliases(true).listing_rewrite(&HashSet::new()).unwrap();
+        assert_eq!(names(&mut r, openai, "id"), ["chat-default"]);
+    }
+
+    #[test]
+    fn test_routes_by_glob_in_order() {
+        let cfg: ModelsConfig = serde_yaml::from_str(
+            "routes:
+               - { models: [llama3.1:70b*], service: gpu-big }
+               - { models: ['llama3*', mistral], service: gpu-a }
+               - { models: ['mxbai-embed*', 'nomic-embed-text:v?.?'], service: cpu-embed }",
+        )
+        .unwrap();
+        let r = ModelRouter::new(&cfg);
+        assert_eq!(
+            r.service_for("llama3.1:70b-instruct-q4_K_M"),
+            Some("gpu-big")
+        );
+        assert_eq!(r.service_for("llama3.1:8b"), Some("gpu-a"));
+        assert_eq!(r.service_for("mistral:latest"), Some("gpu-a"));
+        assert_eq!(r.service_for("mistral:7b"), None);
+        assert_eq!(r.service_for("mxbai-embed-large"), Some("cpu-embed"));
+        assert_eq!(r.service_for("nomic-embed-text:v1.5"), Some("cpu-embed"));
+        assert_eq!(r.service_for("phi3"), None);
+        assert!(glob_match("*a*b", "xaxxb") && !glob_match("*a*b", "xaxxbc"));
+    }
+}
@@ -0,0 +1,241 @@
+// src/oidc.rs
+
+use crate::{
+    config::OidcProvider,
+    jwks::{parse_algorithms, peek_claim, JwksCache, JwksSource, JwtError},
+};
+use jsonwebtoken::{Algorithm, Validation};
+use reqwest::Client as ReqwestClient;
+use serde_json::{Map, Value};
+
+/// Claims of a token that passed every check, tagged with the provider
+/// that vouched for it.
+#[derive(Debug, Clone)]
+pub struct VerifiedToken {
+    pub provider: String,
+    pub claims: Map<String, Value>,
+}
+
+impl VerifiedToken {
+    pub fn claim(&self, name: &str) -> Option<String> {
+        self.claims.get(name)?.as_str().map(str::to_string)
+    }
+}
+
+struct Issuer {
+    name: String,
+    algorithms: Vec<Algorithm>,
+    validation: Validation,
+    jwks: JwksCache,
+}
+
+/// Verifies bearer JWTs against the configured `auth.oidc_providers`.
+pub struct OidcVerifier {
+    issuers: Vec<Issuer>,
+}
+
+impl OidcVerifier {
+    pub fn new(client: ReqwestClient, providers: &[OidcProvider]) -> Self {
+        let issuers = providers
+            .iter()
+            .map(|p| {
+                let issuer = p.issuer_url.trim_end_matches('/').to_string();
+                let source = match &p.jwks_uri {
+                    Some(url) => JwksSource::Direct { url: url.clone() },
+                    None => JwksSource::Discovery {
+                        issuer: issuer.clone(),
+                    },
+                };
+                let mut validation = Validation::new(Algorithm::RS256);
+                validation.set_issuer(&[issuer.clone(), format!("{}/", issuer)]);
+                validation.set_audience(&[&p.audience]);
+                validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+                validation.validate_nbf = true;
+                validation.leeway = p.clock_skew_secs;
+                Issuer {
+                    name: p.name.clone(),
+                    algorithms: parse_algorithms(&p.algorithms),
+                    validation,
+                    jwks: JwksCache::new(client.clone(), source),
+                }
+            })
+            .collect();
+        Self { issuers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issuers.is_empty()
+    }
+
+    /// Pick the provider by the token's `iss`, then verify signature,
+    /// `iss`, `aud`, `exp` and `nbf` against it.
+    pub async fn verify(&self, token: &str) -> Result<VerifiedToken, JwtError> {
+        let iss = peek_claim(token, "iss").ok_or(JwtError::MissingClaim("iss".into()))?;
+        let issuer = self
+            .issuers
+            .iter()
+            .find(|i| {
+                i.validation
+                    .iss
+                    .as_ref()
+                    .map(|set| set.contains(&iss))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| JwtError::UnknownIssuer(iss.clone()))?;
+        let claims = issuer
+            .jwks
+            .verify(token, &issuer.algorithms, &issuer.validation)
+            .await?;
+        Ok(VerifiedToken {
+            provider: issuer.name.clone(),
+            claims,
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use parking_lot::Mutex;
+    use serde_json::json;
+    use std::{convert::Infallible, sync::Arc};
+
+    pub(crate) const KEY_1: &str = include_str!("../testdata/jwt_rsa_1.pem");
+    pub(crate) const KEY_2: &str = include_str!("../testdata/jwt_rsa_2.pem");
+    pub(crate) const JWKS_1: &str = include_str!("../testdata/jwks_1.json");
+    pub(crate) const JWKS_2: &str = include_str!("../testdata/jwks_2.json");
+
+    /// Local IdP stub serving discovery and a swappable JWKS. Returns the
+    /// issuer URL and a handle to replace the key set.
+    pub(crate) async fn jwks_stub(jwks: &str) -> (String, Arc<Mutex<String>>) {
+        let keys = Arc::new(Mutex::new(jwks.to_string()));
+        let served = keys.clone();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let issuer = base.clone();
+        let make_svc = make_service_fn(move |_| {
+            let served = served.clone();
+            let base = base.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let body = match req.uri().path() {
+                        "/.well-known/openid-configuration" => {
+                            json!({ "issuer": base, "jwks_uri": format!("{}/jwks", base) })
+                                .to_string()
+                        }
+                        _ => served.lock().clone(),
+                    };
+                    async move { Ok::<_, Infallible>(Response::new(Body::from(body))) }
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::spawn(server);
+        (issuer, keys)
+    }
+
+    pub(crate) fn sign(key_pem: &str, kid: &str, claims: Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let key = EncodingKey::from_rsa_pem(key_pem.as_bytes()).unwrap();
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    fn provider(issuer: &str) -> OidcProvider {
+        OidcProvider {
+            name: "test".into(),
+            issuer_url: issuer.into(),
+            audience: "gamb".into(),
+            jwks_uri: None,
+            clock_skew_secs: 30,
+            algorithms: vec![],
+        }
+    }
+
+    fn claims(issuer: &str, aud: &str, exp_offset: i64) -> Value {
+        let now = get_current_timestamp() as i64;
+        json!({ "iss": issuer, "aud": aud, "sub": "alice", "exp": now + exp_offset })
+    }
+
+    #[tokio::test]
+    async fn test_valid_and_invalid_tokens() {
+        let (issuer, _) = jwks_stub(JWKS_1).await;
+        let v = OidcVerifier::new(ReqwestClient::new(), &[provider(&issuer)]);
+
+        let ok = sign(KEY_1, "test-key-1", claims(&issuer, "gamb", 300));
+        let t = v.verify(&ok).await.unwrap();
+        assert_eq!(t.claim("sub").as_deref(), Some("alice"));
+
+        let wrong_aud = sign(KEY_1, "test-key-1", claims(&issuer, "other", 300));
+        assert!(matches!(
+            v.verify(&wrong_aud).await,
+            Err(JwtError::InvalidAudience)
+        ));
+
+        let expired = sign(KEY_1, "test-key-1", claims(&issuer, "gamb", -120));
+        assert!(matches!(v.verify(&expired).await, Err(JwtError::Expired)));
+
+        // Within clock skew: expired 10s ago, skew is 30s.
+        let skewed = sign(KEY_1, "test-key-1", claims(&issuer, "gamb", -10));
+        assert!(v.verify(&skewed).await.is_ok());
+
+        let mut future = claims(&issuer, "gamb", 300);
+        future["nbf"] = json!(get_current_timestamp() + 600);
+        let early = sign(KEY_1, "test-key-1", future);
+        assert!(matches!(v.verify(&early).await, Err(JwtError::NotYetValid)));
+
+        let forged = sign(KEY_2, "test-key-1", claims(&issuer, "gamb", 300));
+        assert!(matches!(
+            v.verify(&forged).await,
+            Err(JwtError::InvalidSignature)
+        ));
+
+        let foreign = sign(KEY_1, "test-key-1", claims("https://evil", "gamb", 300));
+        assert!(matches!(
+            v.verify(&foreign).await,
+            Err(JwtError::UnknownIssuer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_kid_refreshes_jwks() {
+        let (issuer, keys) = jwks_stub(JWKS_1).await;
+        let v = OidcVerifier::new(ReqwestClient::new(), &[provider(&issuer)]);
+        let first = sign(KEY_1, "test-key-1", claims(&issuer, "gamb", 300));
+        let rotated = sign(KEY_2, "test-key-2", claims(&issuer, "gamb", 300));
+        assert!(v.verify(&first).await.is_ok());
+
+        // The IdP rotates keys. A refetch right after the last one is
+        // throttled, so the new kid is still unknown.
+        *keys.lock() = JWKS_2.to_string();
+        assert!(matches!(
+            v.verify(&rotated).await,
+            Err(JwtError::UnknownKey(_))
+        ));
+
+        // Without the throttle the unknown kid triggers a refetch.
+        let i = &v.issuers[0];
+        let cache = JwksCache::new(
+            ReqwestClient::new(),
+            JwksSource::Discovery {
+                issuer: issuer.clone(),
+            },
+        )
+        .with_min_refresh(std::time::Duration::ZERO);
+        *keys.lock() = JWKS_1.to_string();
+        assert!(cache
+            .verify(&first, &i.algorithms, &i.validation)
+            .await
+            .is_ok());
+        *keys.lock() = JWKS_2.to_string();
+        assert!(cache
+            .verify(&rotated, &i.algorithms, &i.validation)
+            .await
+            .is_ok());
+    }
+}
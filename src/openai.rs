@@ -0,0 +1,651 @@
+// src/openai.rs
+
+use crate::response_stream::BodyRewrite;
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// OpenAI endpoints served by translating to the native Ollama API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facade {
+    Chat,
+    Completions,
+    Embeddings,
+    Models,
+}
+
+impl Facade {
+    pub fn of(method: &str, path: &str) -> Option<Self> {
+        match (method, path) {
+            ("POST", "/v1/chat/completions") => Some(Facade::Chat),
+            ("POST", "/v1/completions") => Some(Facade::Completions),
+            ("POST", "/v1/embeddings") => Some(Facade::Embeddings),
+            ("GET", "/v1/models") => Some(Facade::Models),
+            _ => None,
+        }
+    }
+
+    pub fn native_path(self) -> &'static str {
+        match self {
+            Facade::Chat => "/api/chat",
+            Facade::Completions => "/api/generate",
+            Facade::Embeddings => "/api/embed",
+            Facade::Models => "/api/tags",
+        }
+    }
+}
+
+/// A native request built from an OpenAI one.
+#[derive(Debug)]
+pub struct NativeRequest {
+    pub facade: Facade,
+    /// Empty for `GET /api/tags`
+    pub body: Bytes,
+    pub model: String,
+    pub stream: bool,
+    pub include_usage: bool,
+}
+
+/// Map an OpenAI request body to its native equivalent.
+pub fn translate_request(facade: Facade, body: &[u8]) -> Result<NativeRequest, String> {
+    if facade == Facade::Models {
+        return Ok(NativeRequest {
+            facade,
+            body: Bytes::new(),
+            model: String::new(),
+            stream: false,
+            include_usage: false,
+        });
+    }
+    let v: Value = serde_json::from_slice(body).map_err(|_| "invalid JSON".to_string())?;
+    let model = v
+        .get("model")
+        .and_then(Value::as_str)
+        .ok_or("missing model")?
+        .to_string();
+    let stream = v.get("stream").and_then(Value::as_bool).unwrap_or(false);
+    let include_usage = v
+        .pointer("/stream_options/include_usage")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let mut out = Map::new();
+    out.insert("model".into(), json!(model));
+    match facade {
+        Facade::Chat => {
+            let messages = v
+                .get("messages")
+                .and_then(Value::as_array)
+                .ok_or("missing messages")?
+                .iter()
+                .map(chat_message)
+                .collect::<Result<Vec<_>, _>>()?;
+            out.insert("messages".into(), Value::Array(messages));
+            out.insert("stream".into(), json!(stream));
+            if let Some(tools) = v.get("tools") {
+                out.insert("tools".into(), tools.clone());
+            }
+        }
+        Facade::Completions => {
+            let prompt = match v.get("prompt") {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Array(items)) if items.len() == 1 && items[0].is_string() => {
+                    items[0].as_str().unwrap_or_default().to_string()
+                }
+                _ => return Err("prompt must be a string".into()),
+            };
+            out.insert("prompt".into(), json!(prompt));
+            if let Some(s) = v.get("suffix") {
+                out.insert("suffix".into(), s.clone());
+            }
+            out.insert("stream".into(), json!(stream));
+        }
+        Facade::Embeddings => {
+            let input = match v.get("input") {
+                Some(Value::String(s)) => json!(s),
+                Some(Value::Array(items)) if items.iter().all(Value::is_string) => {
+                    Value::Array(items.clone())
+                }
+                _ => return Err("input must be a string or an array of strings".into()),
+            };
+            out.insert("input".into(), input);
+            if let Some(d) = v.get("dimensions") {
+                out.insert("dimensions".into(), d.clone());
+            }
+        }
+        Facade::Models => unreachable!(),
+    }
+    if let Some(Value::Object(rf)) = v.get("response_format") {
+        match rf.get("type").and_then(Value::as_str) {
+            Some("json_object") => {
+                out.insert("format".into(), json!("json"));
+            }
+            Some("json_schema") => {
+                if let Some(schema) = rf.get("json_schema").and_then(|s| s.get("schema")) {
+                    out.insert("format".into(), schema.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    let options = sampling_options(&v);
+    if !options.is_empty() {
+        out.insert("options".into(), Value::Object(options));
+    }
+    let body = serde_json::to_vec(&Value::Object(out)).map_err(|e| e.to_string())?;
+    Ok(NativeRequest {
+        facade,
+        body: Bytes::from(body),
+        model,
+        stream: stream && facade != Facade::Embeddings,
+        include_usage,
+    })
+}
+
+fn sampling_options(v: &Value) -> Map<String, Value> {
+    let mut options = Map::new();
+    for (from, to) in [
+        ("temperature", "temperature"),
+        ("top_p", "top_p"),
+        ("seed", "seed"),
+        ("frequency_penalty", "frequency_penalty"),
+        ("presence_penalty", "presence_penalty"),
+        ("max_tokens", "num_predict"),
+        ("max_completion_tokens", "num_predict"),
+    ] {
+        if let Some(x) = v.get(from).filter(|x| !x.is_null()) {
+            options.insert(to.into(), x.clone());
+        }
+    }
+    match v.get("stop") {
+        Some(Value::String(s)) => {
+            options.insert("stop".into(), json!([s]));
+        }
+        Some(stop @ Value::Array(_)) => {
+            options.insert("stop".into(), stop.clone());
+        }
+        _ => {}
+    }
+    options
+}
+
+/// One OpenAI chat message as an Ollama message: content parts are joined,
+/// `data:` image URLs become `images`, and tool call arguments are parsed.
+fn chat_message(m: &Value) -> Result<Value, String> {
+    let role = m.get("role").and_then(Value::as_str).unwrap_or("user");
+    let mut out = Map::new();
+    out.insert("role".into(), json!(role));
+    let mut images = Vec::new();
+    let content = match m.get("content") {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => {
+            let mut text = Vec::new();
+            for p in parts {
+                match p.get("type").and_then(Value::as_str) {
+                    Some("text") => {
+                        text.push(p.get("text").and_then(Value::as_str).unwrap_or_default())
+                    }
+                    Some("image_url") => {
+                        let url = p
+                            .pointer("/image_url/url")
+                            .or_else(|| p.get("image_url"))
+                            .and_then(Value::as_str)
+                            .unwrap_or_default();
+                        let data = url
+                            .strip_prefix("data:")
+                            .and_then(|rest| rest.split_once(";base64,"))
+                            .map(|(_, data)| data)
+                            .ok_or("only data: URLs are supported for images")?;
+                        images.push(json!(data));
+                    }
+                    _ => {}
+                }
+            }
+            text.join("\n")
+        }
+        _ => String::new(),
+    };
+    out.insert("content".into(), json!(content));
+    if !images.is_empty() {
+        out.insert("images".into(), Value::Array(images));
+    }
+    if let Some(calls) = m.get("tool_calls").and_then(Value::as_array) {
+        let calls: Vec<Value> = calls
+            .iter()
+            .map(|c| {
+                let args = match c.pointer("/function/arguments") {
+                    Some(Value::String(s)) => serde_json::from_str(s).unwrap_or(json!({})),
+                    Some(v) => v.clone(),
+                    None => json!({}),
+                };
+                json!({"function": {
+                    "name": c.pointer("/function/name").cloned().unwrap_or(Value::Null),
+                    "arguments": args,
+                }})
+            })
+            .collect();
+        out.insert("tool_calls".into(), Value::Array(calls));
+    }
+    Ok(Value::Object(out))
+}
+
+/// Rewrites native response bodies into OpenAI ones. Streamed generations
+/// are converted line by line from NDJSON to SSE; everything else is
+/// buffered and converted once complete.
+pub struct ResponseTranslator {
+    facade: Facade,
+    stream: bool,
+    include_usage: bool,
+    ok: bool,
+    id: String,
+    model: String,
+    created: u64,
+    buf: Vec<u8>,
+    sent_role: bool,
+    tool_calls: usize,
+}
+
+impl ResponseTranslator {
+    pub fn new(req: &NativeRequest, request_id: &str, ok: bool) -> Self {
+        let prefix = match req.facade {
+            Facade::Chat => "chatcmpl",
+            _ => "cmpl",
+        };
+        Self {
+            facade: req.facade,
+            stream: req.stream && ok,
+            include_usage: req.include_usage,
+            ok,
+            id: format!("{}-{}", prefix, request_id),
+            model: req.model.clone(),
+            created: unix_now(),
+            buf: Vec::new(),
+            sent_role: false,
+            tool_calls: 0,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        if self.stream {
+            "text/event-stream"
+        } else {
+            "application/json"
+        }
+    }
+}
+
+impl BodyRewrite for ResponseTranslator {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.buf.extend_from_slice(chunk);
+        if !self.stream {
+            return Vec::new();
+        }
+        let mut out = Vec::new();
+        while let Some(i) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            self.stream_line(&line, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.buf);
+        if self.stream {
+            let mut out = Vec::new();
+            self.stream_line(&rest, &mut out);
+            out.extend_from_slice(b"data: [DONE]\n\n");
+            return out;
+        }
+        let doc: Value = serde_json::from_slice(&rest).unwrap_or(Value::Null);
+        let translated = if !self.ok {
+            let message = doc
+                .get("error")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(|| String::from_utf8_lossy(&rest).into_owned());
+            error_body(&message)
+        } else {
+            match self.facade {
+                Facade::Chat => self.chat_completion(&doc),
+                Facade::Completions => self.text_completion(&doc),
+                Facade::Embeddings => self.embeddings(&doc),
+                Facade::Models => models(&doc),
+            }
+        };
+        serde_json::to_vec(&translated).unwrap_or_default()
+    }
+}
+
+impl ResponseTranslator {
+    fn stream_line(&mut self, line: &[u8], out: &mut Vec<u8>) {
+        let Ok(v) = serde_json::from_slice::<Value>(line) else {
+            return;
+        };
+        let mut events = Vec::new();
+        if let Some(err) = v.get("error").and_then(Value::as_str) {
+            events.push(error_body(err));
+        } else if self.facade == Facade::Chat {
+            self.chat_events(&v, &mut events);
+        } else {
+            self.completion_events(&v, &mut events);
+        }
+        for e in events {
+            out.extend_from_slice(b"data: ");
+            out.extend_from_slice(&serde_json::to_vec(&e).unwrap_or_default());
+            out.extend_from_slice(b"\n\n");
+        }
+    }
+
+    fn chunk(&self, object: &str, choices: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": object,
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        })
+    }
+
+    fn chat_events(&mut self, v: &Value, events: &mut Vec<Value>) {
+        let mut delta = Map::new();
+        if !self.sent_role {
+            delta.insert("role".into(), json!("assistant"));
+            self.sent_role = true;
+        }
+        let content = v
+            .pointer("/message/content")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !content.is_empty() {
+            delta.insert("content".into(), json!(content));
+        }
+        if let Some(calls) = v.pointer("/message/tool_calls").and_then(Value::as_array) {
+            let calls = self.tool_calls(calls, true);
+            delta.insert("tool_calls".into(), Value::Array(calls));
+        }
+        let done = v.get("done").and_then(Value::as_bool).unwrap_or(false);
+        if !delta.is_empty() {
+            events.push(self.chunk(
+                "chat.completion.chunk",
+                json!([{"index": 0, "delta": delta, "finish_reason": null}]),
+            ));
+        }
+        if done {
+            events.push(self.chunk(
+                "chat.completion.chunk",
+                json!([{"index": 0, "delta": {}, "finish_reason": self.finish_reason(v)}]),
+            ));
+            if self.include_usage {
+                let mut usage = self.chunk("chat.completion.chunk", json!([]));
+                usage["usage"] = usage_of(v);
+                events.push(usage);
+            }
+        }
+    }
+
+    fn completion_events(&mut self, v: &Value, events: &mut Vec<Value>) {
+        let text = v
+            .get("response")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let done = v.get("done").and_then(Value::as_bool).unwrap_or(false);
+        if !text.is_empty() || done {
+            let finish = if done {
+                json!(self.finish_reason(v))
+            } else {
+                Value::Null
+            };
+            events.push(self.chunk(
+                "text_completion",
+                json!([{"index": 0, "text": text, "logprobs": null, "finish_reason": finish}]),
+            ));
+        }
+        if done && self.include_usage {
+            let mut usage = self.chunk("text_completion", json!([]));
+            usage["usage"] = usage_of(v);
+            events.push(usage);
+        }
+    }
+
+    fn finish_reason(&self, v: &Value) -> &'static str {
+        if self.tool_calls > 0 {
+            return "tool_calls";
+        }
+        match v.get("done_reason").and_then(Value::as_str) {
+            Some("length") => "length",
+            _ => "stop",
+        }
+    }
+
+    fn tool_calls(&mut self, calls: &[Value], indexed: bool) -> Vec<Value> {
+        calls
+            .iter()
+            .map(|c| {
+                let n = self.tool_calls;
+                self.tool_calls += 1;
+                let args = c
+                    .pointer("/function/arguments")
+                    .map(|a| match a {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .unwrap_or_else(|| "{}".into());
+                let mut call = json!({
+                    "id": format!("call_{}", n),
+                    "type": "function",
+                    "function": {
+                        "name": c.pointer("/function/name").cloned().unwrap_or(Value::Null),
+                        "arguments": args,
+                    },
+                });
+                if indexed {
+                    call["index"] = json!(n);
+                }
+                call
+            })
+            .collect()
+    }
+
+    fn chat_completion(&mut self, v: &Value) -> Value {
+        let mut message = json!({
+            "role": "assistant",
+            "content": v.pointer("/message/content").and_then(Value::as_str).unwrap_or_default(),
+        });
+        if let Some(calls) = v.pointer("/message/tool_calls").and_then(Value::as_array) {
+            message["tool_calls"] = Value::Array(self.tool_calls(calls, false));
+        }
+        let mut out = self.chunk(
+            "chat.completion",
+            json!([{"index": 0, "message": message, "finish_reason": self.finish_reason(v)}]),
+        );
+        out["usage"] = usage_of(v);
+        out
+    }
+
+    fn text_completion(&self, v: &Value) -> Value {
+        let text = v
+            .get("response")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let mut out = self.chunk(
+            "text_completion",
+            json!([{"index": 0, "text": text, "logprobs": null, "finish_reason": self.finish_reason(v)}]),
+        );
+        out["usage"] = usage_of(v);
+        out
+    }
+
+    fn embeddings(&self, v: &Value) -> Value {
+        let data: Vec<Value> = v
+            .get("embeddings")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, e)| json!({"object": "embedding", "index": i, "embedding": e}))
+            .collect();
+        let prompt = v
+            .get("prompt_eval_count")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        json!({
+            "object": "list",
+            "data": data,
+            "model": self.model,
+            "usage": {"prompt_tokens": prompt, "total_tokens": prompt},
+        })
+    }
+}
+
+fn usage_of(v: &Value) -> Value {
+    let prompt = v
+        .get("prompt_eval_count")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let completion = v.get("eval_count").and_then(Value::as_u64).unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt,
+        "completion_tokens": completion,
+        "total_tokens": prompt + completion,
+    })
+}
+
+fn models(v: &Value) -> Value {
+    let data: Vec<Value> = v
+        .get("models")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|m| {
+            let id = m.get("name").or_else(|| m.get("model"))?.as_str()?;
+            let created = m
+                .get("modified_at")
+                .and_then(Value::as_str)
+                .and_then(|t| humantime::parse_rfc3339_weak(t.get(..19)?).ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            Some(json!({"id": id, "object": "model", "created": created, "owned_by": "library"}))
+        })
+        .collect();
+    json!({"object": "list", "data": data})
+}
+
+pub fn error_body(message: &str) -> Value {
+    json!({"error": {"message": message, "type": "invalid_request_error", "param": null, "code": null}})
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_request_translation() {
+        let body = br#"{
+            "model": "llama3",
+            "messages": [
+                {"role": "system", "content": "be brief"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBOR"}}
+                ]}
+            ],
+            "max_tokens": 64,
+            "temperature": 0.2,
+            "stop": "END",
+            "response_format": {"type": "json_object"},
+            "stream": true,
+            "stream_options": {"include_usage": true}
+        }"#;
+        let req = translate_request(Facade::Chat, body).unwrap();
+        assert!(req.stream && req.include_usage);
+        let native: Value = serde_json::from_slice(&req.body).unwrap();
+        assert_eq!(native["messages"][1]["content"], "what is this?");
+        assert_eq!(native["messages"][1]["images"][0], "iVBOR");
+        assert_eq!(native["options"]["num_predict"], 64);
+        assert_eq!(native["options"]["stop"][0], "END");
+        assert_eq!(native["format"], "json");
+        assert_eq!(native["stream"], true);
+
+        let remote = br#"{"model":"m","messages":[{"role":"user","content":[{"type":"image_url","image_url":{"url":"https://x/y.png"}}]}]}"#;
+        assert!(translate_request(Facade::Chat, remote).is_err());
+    }
+
+    #[test]
+    fn test_streamed_chat_to_sse() {
+        let req = translate_request(
+            Facade::Chat,
+            br#"{"model":"m","messages":[],"stream":true,"stream_options":{"include_usage":true}}"#,
+        )
+        .unwrap();
+        let mut tr = ResponseTranslator::new(&req, "abc", true);
+        assert_eq!(tr.content_type(), "text/event-stream");
+        let mut out = tr.feed(b"{\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"},\"done\":false}\n{\"message\":{\"content\":\"\"},\"done\":true,");
+        out.extend(
+            tr.feed(b"\"done_reason\":\"length\",\"prompt_eval_count\":3,\"eval_count\":1}\n"),
+        );
+        out.extend(tr.finish());
+        let text = String::from_utf8(out).unwrap();
+        let events: Vec<&str> = text
+            .split("\n\n")
+            .filter_map(|e| e.strip_prefix("data: "))
+            .collect();
+        assert_eq!(events.len(), 4);
+        let first: Value = serde_json::from_str(events[0]).unwrap();
+        assert_eq!(first["id"], "chatcmpl-abc");
+        assert_eq!(first["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(first["choices"][0]["delta"]["content"], "Hi");
+        let last: Value = serde_json::from_str(events[1]).unwrap();
+        assert_eq!(last["choices"][0]["finish_reason"], "length");
+        let usage: Value = serde_json::from_str(events[2]).unwrap();
+        assert_eq!(usage["usage"]["total_tokens"], 4);
+        assert_eq!(events[3], "[DONE]");
+    }
+
+    #[test]
+    fn test_buffered_responses() {
+        let req = translate_request(Facade::Chat, br#"{"model":"m","messages":[]}"#).unwrap();
+        let mut tr = ResponseTranslator::new(&req, "r1", true);
+        assert!(tr
+            .feed(br#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"f","arguments":{"a":1}}}]},"#)
+            .is_empty());
+        tr.feed(br#""done":true,"done_reason":"stop","prompt_eval_count":5,"eval_count":2}"#);
+        let v: Value = serde_json::from_slice(&tr.finish()).unwrap();
+        assert_eq!(v["object"], "chat.completion");
+        assert_eq!(v["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(
+            v["choices"][0]["message"]["tool_calls"][0]["function"]["arguments"],
+            r#"{"a":1}"#
+        );
+        assert_eq!(v["usage"]["completion_tokens"], 2);
+
+        let req =
+            translate_request(Facade::Embeddings, br#"{"model":"e","input":["a","b"]}"#).unwrap();
+        let mut tr = ResponseTranslator::new(&req, "r2", true);
+        tr.feed(br#"{"embeddings":[[0.1],[0.2]],"prompt_eval_count":2}"#);
+        let v: Value = serde_json::from_slice(&tr.finish()).unwrap();
+        assert_eq!(v["data"][1]["index"], 1);
+        assert_eq!(v["data"][1]["embedding"][0], 0.2);
+
+        let mut tr = ResponseTranslator::new(&req, "r3", false);
+        tr.feed(br#"{"error":"model 'e' not found"}"#);
+        let v: Value = serde_json::from_slice(&tr.finish()).unwrap();
+        assert_eq!(v["error"]["message"], "model 'e' not found");
+
+        let req = translate_request(Facade::Models, b"").unwrap();
+        let mut tr = ResponseTranslator::new(&req, "r4", true);
+        tr.feed(
+            br#"{"models":[{"name":"llama3:8b","modified_at":"2024-05-01T10:20:30.5-07:00"}]}"#,
+        );
+        let v: Value = serde_json::from_slice(&tr.finish()).unwrap();
+        assert_eq!(v["data"][0]["id"], "llama3:8b");
+        assert!(v["data"][0]["created"].as_u64().unwrap() > 0);
+    }
+}
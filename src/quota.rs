@@ -0,0 +1,231 @@
+// src/quota.rs
+
+use crate::config::{QuotaConfig, QuotaLimit};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 24 * HOUR;
+/// Rolling windows: the last 24 hours and the last 30 days.
+const DAILY_BUCKETS: u64 = 24;
+const MONTHLY_BUCKETS: u64 = 30;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    #[error("daily token quota of {limit} exhausted")]
+    Daily { limit: u64, retry_after_secs: u64 },
+    #[error("monthly token quota of {limit} exhausted")]
+    Monthly { limit: u64, retry_after_secs: u64 },
+}
+
+impl QuotaExceeded {
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            QuotaExceeded::Daily {
+                retry_after_secs, ..
+            }
+            | QuotaExceeded::Monthly {
+                retry_after_secs, ..
+            } => *retry_after_secs,
+        }
+    }
+}
+
+/// Token counts bucketed by hour (for the daily window) and by day (for
+/// the monthly window). Buckets older than the window are dropped.
+#[derive(Debug, Default)]
+struct Usage {
+    hours: VecDeque<(u64, u64)>,
+    days: VecDeque<(u64, u64)>,
+}
+
+impl Usage {
+    fn add(&mut self, now: u64, tokens: u64) {
+        for (buckets, width) in [(&mut self.hours, HOUR), (&mut self.days, DAY)] {
+            let slot = now / width;
+            match buckets.back_mut() {
+                Some((s, t)) if *s == slot => *t += tokens,
+                _ => buckets.push_back((slot, tokens)),
+            }
+        }
+    }
+
+    fn expire(&mut self, now: u64) {
+        let hour = now / HOUR;
+        while matches!(self.hours.front(), Some((s, _)) if s + DAILY_BUCKETS <= hour) {
+            self.hours.pop_front();
+        }
+        let day = now / DAY;
+        while matches!(self.days.front(), Some((s, _)) if s + MONTHLY_BUCKETS <= day) {
+            self.days.pop_front();
+        }
+    }
+
+    fn daily(&self) -> u64 {
+        self.hours.iter().map(|(_, t)| t).sum()
+    }
+
+    fn monthly(&self) -> u64 {
+        self.days.iter().map(|(_, t)| t).sum()
+    }
+}
+
+/// Rolling daily/monthly token budgets per client, charged from the usage
+/// fields the model server reports. Usage is kept in memory only.
+pub struct QuotaTracker {
+    default: QuotaLimit,
+    clients: HashMap<String, QuotaLimit>,
+    usage: Arc<Mutex<HashMap<String, Usage>>>,
+}
+
+impl QuotaTracker {
+    pub fn new(cfg: &QuotaConfig) -> Self {
+        Self {
+            default: cfg.default_limit(),
+            clients: cfg.clients.clone(),
+            usage: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Share the usage of a tracker being replaced, so new limits apply to
+    /// what clients have already used, and requests still running under the
+    /// old tracker charge the same totals.
+    pub fn share_usage(&mut self, old: &QuotaTracker) {
+        self.usage = old.usage.clone();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.default.is_limited() || self.clients.values().any(QuotaLimit::is_limited)
+    }
+
+    /// Client overrides win field by field; a limit of 0 means unlimited.
+    fn limits(&self, client: &str) -> QuotaLimit {
+        let over = self.clients.get(client).copied().unwrap_or_default();
+        QuotaLimit {
+            daily_tokens: over
+                .daily_tokens
+                .or(self.default.daily_tokens)
+                .filter(|&n| n > 0),
+            monthly_tokens: over
+                .monthly_tokens
+                .or(self.default.monthly_tokens)
+                .filter(|&n| n > 0),
+        }
+    }
+
+    /// Reject the request if the client has used up a budget.
+    pub fn check(&self, client: &str) -> Result<(), QuotaExceeded> {
+        self.check_at(client, unix_now())
+    }
+
+    /// Add `tokens` to the client's usage.
+    pub fn charge(&self, client: &str, tokens: u64) {
+        self.charge_at(client, tokens, unix_now());
+    }
+
+    /// Tokens used by `client` in the current daily and monthly windows.
+    pub fn used(&self, client: &str) -> (u64, u64) {
+        let now = unix_now();
+        let mut usage = self.usage.lock();
+        usage
+            .get_mut(client)
+            .map(|u| {
+                u.expire(now);
+                (u.daily(), u.monthly())
+            })
+            .unwrap_or((0, 0))
+    }
+
+    fn check_at(&self, client: &str, now: u64) -> Result<(), QuotaExceeded> {
+        let limits = self.limits(client);
+        let mut usage = self.usage.lock();
+        let Some(u) = usage.get_mut(client) else {
+            return Ok(());
+        };
+        u.expire(now);
+        if let Some(limit) = limits.daily_tokens {
+            if u.daily() >= limit {
+                let oldest = u.hours.front().map(|(s, _)| *s).unwrap_or(now / HOUR);
+                return Err(QuotaExceeded::Daily {
+                    limit,
+                    retry_after_secs: ((oldest + DAILY_BUCKETS) * HOUR).saturating_sub(now),
+                });
+            }
+        }
+        if let Some(limit) = limits.monthly_tokens {
+            if u.monthly() >= limit {
+                let oldest = u.days.front().map(|(s, _)| *s).unwrap_or(now / DAY);
+                return Err(QuotaExceeded::Monthly {
+                    limit,
+                    retry_after_secs: ((oldest + MONTHLY_BUCKETS) * DAY).saturating_sub(now),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn charge_at(&self, client: &str, tokens: u64, now: u64) {
+        if tokens == 0 {
+            return;
+        }
+        let mut usage = self.usage.lock();
+        let u = usage.entry(client.to_string()).or_default();
+        u.expire(now);
+        u.add(now, tokens);
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_daily_and_monthly_limits() {
+        let cfg = QuotaConfig {
+            daily_tokens: Some(100),
+            monthly_tokens: Some(250),
+            clients: HashMap::from([(
+                "sub:batch".to_string(),
+                QuotaLimit {
+                    daily_tokens: Some(0),
+                    monthly_tokens: Some(0),
+                },
+            )]),
+        };
+        let q = QuotaTracker::new(&cfg);
+        let t0 = 1_700_000_000;
+
+        q.charge_at("sub:a", 60, t0);
+        assert!(q.check_at("sub:a", t0).is_ok());
+        q.charge_at("sub:a", 60, t0 + 10);
+        let err = q.check_at("sub:a", t0 + 20).unwrap_err();
+        assert!(matches!(err, QuotaExceeded::Daily { limit: 100, .. }));
+        assert!(err.retry_after_secs() <= DAY);
+
+        // A day later the hourly buckets have rolled off.
+        assert!(q.check_at("sub:a", t0 + DAY + HOUR).is_ok());
+        q.charge_at("sub:a", 90, t0 + DAY + HOUR);
+        q.charge_at("sub:a", 90, t0 + 3 * DAY);
+        assert!(matches!(
+            q.check_at("sub:a", t0 + 3 * DAY),
+            Err(QuotaExceeded::Monthly { limit: 250, .. })
+        ));
+
+        // Other clients are tracked separately; overrides can lift limits.
+        assert!(q.check_at("sub:b", t0).is_ok());
+        q.charge_at("sub:batch", 10_000, t0);
+        assert!(q.check_at("sub:batch", t0).is_ok());
+    }
+}
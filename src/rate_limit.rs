@@ -0,0 +1,190 @@
+// src/rate_limit.rs
+
+use crate::{
+    auth::{AuthMethod, Identity},
+    config::ClassLimit,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// Endpoints that share a bucket. Generations cost far more than a model
+/// listing, so each class is limited separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    Generate,
+    Embed,
+    Tags,
+    Other,
+}
+
+impl RouteClass {
+    pub fn of(path: &str) -> Self {
+        match path {
+            "/api/generate" | "/api/chat" | "/v1/chat/completions" | "/v1/completions" => {
+                RouteClass::Generate
+            }
+            "/api/embed" | "/api/embeddings" | "/v1/embeddings" => RouteClass::Embed,
+            "/api/tags" | "/api/ps" | "/api/show" | "/api/version" | "/v1/models" => {
+                RouteClass::Tags
+            }
+            _ => RouteClass::Other,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteClass::Generate => "generate",
+            RouteClass::Embed => "embed",
+            RouteClass::Tags => "tags",
+            RouteClass::Other => "other",
+        }
+    }
+}
+
+/// Bucket key for a caller: the authenticated subject, else the API key
+/// they presented, else their IP address.
+pub fn client_key(identity: &Identity, ip: IpAddr) -> String {
+    if let Some(key) = &identity.api_key {
+        return format!("key:{}", key.id);
+    }
+    if let Some(sub) = &identity.subject {
+        return format!("sub:{}", sub);
+    }
+    match identity.method {
+        AuthMethod::StaticToken => "key:static".to_string(),
+        _ => format!("ip:{}", ip),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    per_sec: f64,
+    burst: f64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Result of a rate-limit check, carrying the values for the
+/// `X-RateLimit-*` and `Retry-After` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed
+    pub retry_after_secs: u64,
+}
+
+/// Token buckets keyed by (client, route class).
+pub struct RateLimiter {
+    default: Option<Limit>,
+    classes: HashMap<RouteClass, Option<Limit>>,
+    buckets: Mutex<HashMap<(String, RouteClass), Bucket>>,
+    last_sweep: Mutex<Instant>,
+}
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+impl RateLimiter {
+    /// `per_sec` of 0 disables limiting; `burst` of 0 means one second's
+    /// worth of requests. Class overrides use the same rules.
+    pub fn new(per_sec: u32, burst: u32, classes: &HashMap<String, ClassLimit>) -> Self {
+        let limit = |per_sec: u32, burst: u32| {
+            (per_sec > 0).then_some(Limit {
+                per_sec: per_sec as f64,
+                burst: if burst == 0 { per_sec } else { burst } as f64,
+            })
+        };
+        let classes = [
+            RouteClass::Generate,
+            RouteClass::Embed,
+            RouteClass::Tags,
+            RouteClass::Other,
+        ]
+        .into_iter()
+        .filter_map(|c| {
+            classes
+                .get(c.as_str())
+                .map(|l| (c, limit(l.per_sec, l.burst)))
+        })
+        .collect();
+        Self {
+            default: limit(per_sec, burst),
+            classes,
+            buckets: Mutex::new(HashMap::new()),
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Take one token from the caller's bucket for `class`.
+    /// Returns `None` when the class is unlimited.
+    pub fn check(&self, client: &str, class: RouteClass) -> Option<Decision> {
+        let limit = self.classes.get(&class).copied().unwrap_or(self.default)?;
+        let now = Instant::now();
+        self.sweep(now);
+
+        let mut buckets = self.buckets.lock();
+        let bucket = buckets
+            .entry((client.to_string(), class))
+            .or_insert(Bucket {
+                tokens: limit.burst,
+                updated: now,
+            });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_sec).min(limit.burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let missing = limit.burst - bucket.tokens;
+        Some(Decision {
+            allowed,
+            limit: limit.burst as u64,
+            remaining: bucket.tokens.floor() as u64,
+            reset_secs: (missing / limit.per_sec).ceil() as u64,
+            retry_after_secs: if allowed {
+                0
+            } else {
+                ((1.0 - bucket.tokens) / limit.per_sec).ceil().max(1.0) as u64
+            },
+        })
+    }
+
+    /// Move the buckets of a limiter being replaced into this one. Changed
+    /// limits apply from each bucket's next check.
+    pub fn take_buckets(&self, old: &RateLimiter) {
+        *self.buckets.lock() = std::mem::take(&mut *old.buckets.lock());
+    }
+
+    /// Drop buckets that have refilled completely; they hold no state.
+    fn sweep(&self, now: Instant) {
+        let mut last = self.last_sweep.lock();
+        if now.duration_since(*last) < SWEEP_INTERVAL {
+            return;
+        }
+        *last = now;
+        drop(last);
+        let max = self
+            .classes
+            .values()
+            .flatten()
+            .chain(self.default.iter())
+            .map(|l| l.burst / l.per_sec)
+            .fold(0.0, f64::max);
+        self.buckets
+            .lock()
+            .retain(|_, b| now.duration_since(b.updated).as_secs_f64() < max);
+    }
+}
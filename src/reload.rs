@@ -0,0 +1,223 @@
+// src/reload.rs
+
+use crate::{
+    admission::{Admission, AdmissionError},
+    audit::{AuditError, AuditLog},
+    backend_registry::{BackendRegistry, OutlierError, OutlierPolicy},
+    cache::{CacheError, ResponseCache},
+    config::{AuditConfig, CacheConfig, Config, ConfigError},
+    content_rules::{ContentRuleError, ContentRules},
+    dlp::{DlpError, DlpScanner},
+    health::{HealthChecker, HealthError},
+    http_proxy::{GatewayState, Unchanged},
+    key_store::{KeyStore, KeyStoreError},
+    retry::{RetryError, RetryPolicy},
+};
+use log::{error, info, warn};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::AbortHandle,
+};
+
+#[derive(Debug, Error)]
+pub enum ReloadError {
+    #[error("{0}")]
+    Config(#[from] ConfigError),
+    #[error("key store: {0}")]
+    Keys(#[from] KeyStoreError),
+    #[error("DLP: {0}")]
+    Dlp(#[from] DlpError),
+    #[error("content rules: {0}")]
+    Rules(#[from] ContentRuleError),
+    #[error("retry: {0}")]
+    Retry(#[from] RetryError),
+    #[error("outlier detection: {0}")]
+    Outlier(#[from] OutlierError),
+    #[error("health checks: {0}")]
+    Health(#[from] HealthError),
+    #[error("audit log: {0}")]
+    Audit(#[from] AuditError),
+    #[error("response cache: {0}")]
+    Cache(#[from] CacheError),
+    #[error("concurrency: {0}")]
+    Admission(#[from] AdmissionError),
+    #[error("invalid reload interval '{0}': {1}")]
+    Interval(String, humantime::DurationError),
+}
+
+/// Settings only read at startup, compared to warn that a reload did not
+/// apply them.
+#[derive(Debug, PartialEq)]
+struct Listeners {
+    ports: [Option<u16>; 5],
+    binds: [Option<String>; 3],
+    tls: (String, String),
+}
+
+impl Listeners {
+    fn of(cfg: &Config) -> Self {
+        Self {
+            ports: [
+                Some(cfg.http_port),
+                cfg.https_port,
+                cfg.grpc_port,
+                cfg.tcp_port,
+                cfg.udp_port,
+            ],
+            binds: [
+                cfg.http_bind_addr.clone(),
+                cfg.https_bind_addr.clone(),
+                cfg.grpc_bind_addr.clone(),
+            ],
+            tls: (cfg.tls.cert_path.clone(), cfg.tls.key_path.clone()),
+        }
+    }
+}
+
+/// Re-reads the config file on SIGHUP, or when it changes if `reload.watch`
+/// is set, and swaps it into the running gateway. A file that fails to
+/// load leaves the current config in place.
+pub struct Reloader {
+    path: PathBuf,
+    state: Arc<GatewayState>,
+    registry: Arc<BackendRegistry>,
+    health: Vec<AbortHandle>,
+    listeners: Listeners,
+    /// Config of the running audit log and cache, which are only replaced
+    /// when it changes
+    audit: Option<AuditConfig>,
+    cache: Option<CacheConfig>,
+    /// `None` when not watching the file
+    watch: Option<Duration>,
+    modified: Option<SystemTime>,
+}
+
+impl Reloader {
+    pub fn new(
+        path: impl Into<PathBuf>,
+        cfg: &Config,
+        state: Arc<GatewayState>,
+        registry: Arc<BackendRegistry>,
+        health: Vec<AbortHandle>,
+    ) -> Result<Self, ReloadError> {
+        let watch = cfg
+            .reload
+            .watch
+            .then(|| humantime::parse_duration(&cfg.reload.interval))
+            .transpose()
+            .map_err(|e| ReloadError::Interval(cfg.reload.interval.clone(), e))?;
+        let path = path.into();
+        Ok(Self {
+            modified: modified(&path),
+            listeners: Listeners::of(cfg),
+            audit: cfg.audit.clone(),
+            cache: cfg.cache.clone(),
+            path,
+            state,
+            registry,
+            health,
+            watch,
+        })
+    }
+
+    /// Handle reload triggers, forever.
+    pub async fn run(mut self) {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                error!("Cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => info!("SIGHUP: reloading {}", self.path.display()),
+                _ = self.changed() => info!("{} changed, reloading", self.path.display()),
+            }
+            self.modified = modified(&self.path);
+            match self.reload() {
+                Ok(()) => info!("Reloaded {}", self.path.display()),
+                Err(e) => error!("Config reload failed, keeping the current config: {}", e),
+            }
+        }
+    }
+
+    /// Resolves once the file's modification time moves; never when not
+    /// watching.
+    async fn changed(&self) {
+        let Some(interval) = self.watch else {
+            return std::future::pending().await;
+        };
+        loop {
+            tokio::time::sleep(interval).await;
+            if modified(&self.path) != self.modified {
+                return;
+            }
+        }
+    }
+
+    /// Build everything from the file first, so nothing is applied unless
+    /// all of it is valid.
+    fn reload(&mut self) -> Result<(), ReloadError> {
+        let cfg = Config::from_file(&self.path)?;
+        let keys = match &cfg.key_store {
+            Some(path) => KeyStore::load(path)?,
+            None => KeyStore::default(),
+        };
+        let dlp = DlpScanner::new(&cfg.dlp)?;
+        let rules = ContentRules::new(&cfg.content_rules)?;
+        let retry = cfg.retry.as_ref().map(RetryPolicy::new).transpose()?;
+        let outliers = cfg
+            .outlier_detection
+            .as_ref()
+            .map(OutlierPolicy::new)
+            .transpose()?;
+        let health = HealthChecker::new(&cfg.backends, self.registry.clone())?;
+        let admission = cfg.concurrency.as_ref().map(Admission::new).transpose()?;
+        let unchanged = Unchanged {
+            audit: cfg.audit == self.audit,
+            cache: cfg.cache == self.cache,
+        };
+        let audit = match &cfg.audit {
+            Some(a) if !unchanged.audit => Some(AuditLog::open(a)?),
+            _ => None,
+        };
+        let cache = match &cfg.cache {
+            Some(c) if !unchanged.cache => Some(ResponseCache::new(c)?),
+            _ => None,
+        };
+
+        if Listeners::of(&cfg) != self.listeners {
+            warn!("Listener ports, addresses and TLS files only change on restart");
+        }
+        self.registry.sync(&cfg.backends);
+        self.registry.set_outlier_policy(outliers);
+        for task in self.health.drain(..) {
+            task.abort();
+        }
+        self.health = health.spawn();
+        self.state.reload(
+            GatewayState::new(&cfg, self.registry.clone(), keys)
+                .with_audit(audit)
+                .with_dlp(dlp)
+                .with_content_rules(rules)
+                .with_cache(cache)
+                .with_admission(admission)
+                .with_retry(retry),
+            unchanged,
+        );
+        self.audit = cfg.audit;
+        self.cache = cfg.cache;
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
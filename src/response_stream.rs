@@ -0,0 +1,219 @@
+// src/response_stream.rs
+
+use crate::{
+    audit::BodyCapture,
+    usage::{TokenUsage, UsageScanner},
+};
+use bytes::Bytes;
+use futures_core::Stream;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Outcome of a proxied response body, reported once when the stream ends
+/// or is dropped by the client.
+#[derive(Debug, Clone)]
+pub struct StreamSummary {
+    /// Number of body bytes handed to the client
+    pub bytes: u64,
+    /// `true` if the upstream body reached its end without error
+    pub completed: bool,
+    /// `true` if the upstream body ended with an error
+    pub failed: bool,
+    /// Token counts found in the body, if usage scanning was enabled
+    pub usage: Option<TokenUsage>,
+    /// The body as recorded by [`MeteredStream::capture_body`]
+    pub body: Option<String>,
+    /// The complete body, if buffering was enabled, the stream completed
+    /// and it fit within the limit
+    pub buffered: Option<Bytes>,
+}
+
+type FinishFn = Box<dyn FnOnce(StreamSummary) + Send>;
+
+/// Pass-through wrapper around an upstream body stream.
+///
+/// Chunks are forwarded as soon as the upstream yields them; the stream is
+/// only polled when hyper is ready to write, so backpressure from the client
+/// propagates to the upstream connection. `on_finish` runs exactly once.
+pub struct MeteredStream<S> {
+    inner: S,
+    bytes: u64,
+    completed: bool,
+    failed: bool,
+    usage: Option<UsageScanner>,
+    capture: Option<BodyCapture>,
+    buffer: Option<Vec<u8>>,
+    buffer_limit: usize,
+    on_finish: Option<FinishFn>,
+}
+
+impl<S> MeteredStream<S> {
+    pub fn new<F>(inner: S, on_finish: F) -> Self
+    where
+        F: FnOnce(StreamSummary) + Send + 'static,
+    {
+        Self {
+            inner,
+            bytes: 0,
+            completed: false,
+            failed: false,
+            usage: None,
+            capture: None,
+            buffer: None,
+            buffer_limit: 0,
+            on_finish: Some(Box::new(on_finish)),
+        }
+    }
+
+    /// Look for token usage fields in the body as it passes through.
+    pub fn scan_usage(mut self) -> Self {
+        self.usage = Some(UsageScanner::default());
+        self
+    }
+
+    /// Record the body for the audit log as it passes through.
+    pub fn capture_body(mut self, capture: Option<BodyCapture>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Keep a copy of the body, up to `limit` bytes, for the response cache.
+    pub fn buffer_body(mut self, limit: usize) -> Self {
+        self.buffer = Some(Vec::new());
+        self.buffer_limit = limit;
+        self
+    }
+
+    fn finish(&mut self) {
+        if let Some(f) = self.on_finish.take() {
+            f(StreamSummary {
+                bytes: self.bytes,
+                completed: self.completed,
+                failed: self.failed,
+                usage: self.usage.take().and_then(UsageScanner::finish),
+                body: self.capture.take().map(BodyCapture::finish),
+                buffered: self
+                    .buffer
+                    .take()
+                    .filter(|_| self.completed)
+                    .map(Bytes::from),
+            });
+        }
+    }
+}
+
+impl<S, E> Stream for MeteredStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                self.bytes += chunk.len() as u64;
+                if let Some(scanner) = self.usage.as_mut() {
+                    scanner.feed(&chunk);
+                }
+                if let Some(capture) = self.capture.as_mut() {
+                    capture.feed(&chunk);
+                }
+                let limit = self.buffer_limit;
+                if let Some(buf) = self.buffer.as_mut() {
+                    if buf.len() + chunk.len() > limit {
+                        self.buffer = None;
+                    } else {
+                        buf.extend_from_slice(&chunk);
+                    }
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(Some(Err(e))) => {
+                self.failed = true;
+                self.finish();
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(None) => {
+                self.completed = true;
+                self.finish();
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S> Drop for MeteredStream<S> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// Rewrites a body as it streams past. `finish` flushes anything held back
+/// once the upstream body has ended.
+pub trait BodyRewrite {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8>;
+    fn finish(&mut self) -> Vec<u8>;
+    /// `true` once nothing more should be read from the upstream.
+    fn done(&self) -> bool {
+        false
+    }
+}
+
+/// Runs an upstream body through a [`BodyRewrite`].
+pub struct RewriteStream<S, R> {
+    inner: S,
+    rewrite: R,
+    done: bool,
+}
+
+impl<S, R> RewriteStream<S, R> {
+    pub fn new(inner: S, rewrite: R) -> Self {
+        Self {
+            inner,
+            rewrite,
+            done: false,
+        }
+    }
+}
+
+impl<S, R, E> Stream for RewriteStream<S, R>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    R: BodyRewrite + Unpin,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if self.done {
+                return Poll::Ready(None);
+            }
+            if self.rewrite.done() {
+                self.done = true;
+                let out = self.rewrite.finish();
+                return Poll::Ready((!out.is_empty()).then(|| Ok(Bytes::from(out))));
+            }
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    let out = self.rewrite.feed(&chunk);
+                    if !out.is_empty() {
+                        return Poll::Ready(Some(Ok(Bytes::from(out))));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(None) => {
+                    self.done = true;
+                    let out = self.rewrite.finish();
+                    return Poll::Ready((!out.is_empty()).then(|| Ok(Bytes::from(out))));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
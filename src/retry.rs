@@ -0,0 +1,146 @@
+// src/retry.rs
+
+use crate::config::RetryConfig;
+use parking_lot::Mutex;
+use rand::Rng;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// Seconds of history the retry budget looks at.
+const BUDGET_WINDOW_SECS: u64 = 10;
+
+#[derive(Debug, Error)]
+pub enum RetryError {
+    #[error("invalid retry backoff '{0}': {1}")]
+    Backoff(String, humantime::DurationError),
+}
+
+/// Caps retries at a share of recent requests, so a failing upstream does
+/// not get hit with extra load from every client at once.
+pub struct RetryBudget {
+    percent: u64,
+    min_per_sec: u64,
+    start: Instant,
+    /// `(second, requests, retries)`, oldest first
+    window: Mutex<VecDeque<(u64, u64, u64)>>,
+}
+
+impl RetryBudget {
+    fn new(percent: u32, min_per_sec: u32) -> Self {
+        Self {
+            percent: percent as u64,
+            min_per_sec: min_per_sec as u64,
+            start: Instant::now(),
+            window: Mutex::default(),
+        }
+    }
+
+    /// The current bucket, after dropping those out of the window.
+    fn bucket<'a>(&self, window: &'a mut VecDeque<(u64, u64, u64)>) -> &'a mut (u64, u64, u64) {
+        let now = self.start.elapsed().as_secs();
+        while matches!(window.front(), Some((s, _, _)) if s + BUDGET_WINDOW_SECS <= now) {
+            window.pop_front();
+        }
+        if window.back().is_none_or(|(s, _, _)| *s != now) {
+            window.push_back((now, 0, 0));
+        }
+        window.back_mut().expect("bucket was just pushed")
+    }
+
+    pub fn record_request(&self) {
+        let mut window = self.window.lock();
+        self.bucket(&mut window).1 += 1;
+    }
+
+    /// Spend a retry if the budget allows one.
+    pub fn try_retry(&self) -> bool {
+        let mut window = self.window.lock();
+        self.bucket(&mut window);
+        let (requests, retries) = window
+            .iter()
+            .fold((0, 0), |(q, r), (_, bq, br)| (q + bq, r + br));
+        let allowed = (requests * self.percent / 100).max(self.min_per_sec * BUDGET_WINDOW_SECS);
+        if retries >= allowed {
+            return false;
+        }
+        self.bucket(&mut window).2 += 1;
+        true
+    }
+}
+
+/// When and how often a failed upstream request is tried again.
+pub struct RetryPolicy {
+    max_attempts: u32,
+    statuses: Vec<u16>,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    pub budget: RetryBudget,
+}
+
+impl RetryPolicy {
+    pub fn new(cfg: &RetryConfig) -> Result<Self, RetryError> {
+        let duration = |s: &String| {
+            humantime::parse_duration(s).map_err(|e| RetryError::Backoff(s.clone(), e))
+        };
+        Ok(Self {
+            max_attempts: cfg.max_attempts.max(1),
+            statuses: cfg.statuses.clone(),
+            base_backoff: duration(&cfg.base_backoff)?,
+            max_backoff: duration(&cfg.max_backoff)?,
+            budget: RetryBudget::new(cfg.budget_percent, cfg.min_retries_per_sec),
+        })
+    }
+
+    /// Whether attempt number `attempt` (from 1) may be followed by another.
+    pub fn may_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// Full jitter: a random wait up to `base * 2^(retry - 1)`, capped.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        let cap = self
+            .base_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        cap.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_and_budget() {
+        let policy = RetryPolicy::new(
+            &serde_yaml::from_str(
+                "{ max_attempts: 3, base_backoff: 10ms, max_backoff: 25ms,
+                   budget_percent: 20, min_retries_per_sec: 0 }",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(policy.may_retry(2) && !policy.may_retry(3));
+        assert!(policy.retries_status(503) && !policy.retries_status(500));
+        for retry in 1..6 {
+            let cap = Duration::from_millis([10, 20, 25, 25, 25][retry as usize - 1]);
+            assert!(policy.backoff(retry) <= cap);
+        }
+
+        // 20% of 10 requests: two retries.
+        for _ in 0..10 {
+            policy.budget.record_request();
+        }
+        assert!(policy.budget.try_retry());
+        assert!(policy.budget.try_retry());
+        assert!(!policy.budget.try_retry());
+    }
+}
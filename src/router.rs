@@ -0,0 +1,131 @@
+// src/router.rs
+
+use crate::config::Backend;
+
+/// One entry of `Backend.routes`, e.g. `/echo` or `api.example.com/v1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub host: Option<String>,
+    pub prefix: String,
+    pub service: String,
+}
+
+impl Route {
+    /// Parse a route spec. A leading `/` means "any host"; otherwise the part
+    /// before the first `/` is matched against the request's Host.
+    pub fn parse(spec: &str, service: &str) -> Self {
+        let (host, prefix) = if spec.starts_with('/') {
+            (None, spec.to_string())
+        } else {
+            match spec.find('/') {
+                Some(i) => (Some(spec[..i].to_string()), spec[i..].to_string()),
+                None => (Some(spec.to_string()), "/".to_string()),
+            }
+        };
+        Self {
+            host: host.map(|h| h.to_ascii_lowercase()),
+            prefix,
+            service: service.to_string(),
+        }
+    }
+
+    fn matches_host(&self, host: Option<&str>) -> bool {
+        let Some(want) = &self.host else {
+            return true;
+        };
+        let Some(host) = host else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+        if want.contains(':') {
+            host == *want
+        } else {
+            host.split(':').next() == Some(want.as_str())
+        }
+    }
+
+    /// Prefix match on path-segment boundaries: `/echo` matches `/echo` and
+    /// `/echo/x`, but not `/echoes`.
+    fn matches_path(&self, path: &str) -> bool {
+        if self.prefix == "/" || path == self.prefix {
+            return true;
+        }
+        path.starts_with(&self.prefix)
+            && (self.prefix.ends_with('/') || path.as_bytes().get(self.prefix.len()) == Some(&b'/'))
+    }
+}
+
+/// Longest-prefix router over the `routes` of HTTP backends.
+#[derive(Debug, Clone, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    /// Build the routing table from every `http`/`https` backend.
+    pub fn from_backends(backends: &[Backend]) -> Self {
+        let routes = backends
+            .iter()
+            .filter(|b| matches!(b.protocol.as_str(), "http" | "https"))
+            .flat_map(|b| b.routes.iter().map(move |r| Route::parse(r, &b.name)))
+            .collect();
+        Self::new(routes)
+    }
+
+    pub fn new(mut routes: Vec<Route>) -> Self {
+        // Most specific first: longer prefixes, then host-qualified routes.
+        routes.sort_by(|a, b| {
+            b.prefix
+                .len()
+                .cmp(&a.prefix.len())
+                .then_with(|| b.host.is_some().cmp(&a.host.is_some()))
+        });
+        Self { routes }
+    }
+
+    /// Find the most specific route for `host` and `path`.
+    pub fn resolve(&self, host: Option<&str>, path: &str) -> Option<&Route> {
+        self.routes
+            .iter()
+            .find(|r| r.matches_host(host) && r.matches_path(path))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router() -> Router {
+        Router::new(vec![
+            Route::parse("/api", "ollama"),
+            Route::parse("/api/embed", "embed"),
+            Route::parse("llm.example.com/api", "gpu"),
+            Route::parse("/echo", "echo"),
+        ])
+    }
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let r = router();
+        assert_eq!(r.resolve(None, "/api/embed").unwrap().service, "embed");
+        assert_eq!(r.resolve(None, "/api/chat").unwrap().service, "ollama");
+        assert_eq!(r.resolve(None, "/echo/hello").unwrap().service, "echo");
+        assert!(r.resolve(None, "/echoes").is_none());
+        assert!(r.resolve(None, "/other").is_none());
+    }
+
+    #[test]
+    fn test_host_match() {
+        let r = router();
+        let hit = r
+            .resolve(Some("LLM.example.com:8080"), "/api/chat")
+            .unwrap();
+        assert_eq!(hit.service, "gpu");
+        let miss = r.resolve(Some("other.example.com"), "/api/chat").unwrap();
+        assert_eq!(miss.service, "ollama");
+    }
+}
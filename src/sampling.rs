@@ -0,0 +1,208 @@
+// src/sampling.rs
+
+use crate::config::{Bound, ProxyConfig, SamplingMode};
+use serde_json::{Map, Value};
+
+/// OpenAI request fields that mean `num_predict`.
+const NUM_PREDICT_ALIASES: &[&str] = &["max_tokens", "max_completion_tokens"];
+
+/// Options that must stay integers when clamped.
+const INTEGER_OPTIONS: &[&str] = &["top_k", "num_ctx", "num_predict", "num_gpu", "num_thread"];
+
+/// A sampling parameter the policy does not allow.
+#[derive(Debug, PartialEq)]
+pub struct Violation {
+    /// Label for the policy rejection counter
+    pub reason: &'static str,
+    pub message: String,
+}
+
+/// The sampling part of a [`ProxyConfig`], with `max_num_ctx` and
+/// `max_num_predict` folded into the `num_ctx` and `num_predict` bounds.
+pub struct SamplingLimits<'a> {
+    mode: SamplingMode,
+    deny: &'a [String],
+    bounds: Vec<(&'static str, Bound)>,
+}
+
+impl<'a> SamplingLimits<'a> {
+    pub fn new(cfg: &'a ProxyConfig) -> Self {
+        let s = &cfg.sampling;
+        let capped = |b: Option<Bound>, max: f64| {
+            let mut b = b.unwrap_or_default();
+            b.max = Some(b.max.map_or(max, |m| m.min(max)));
+            Some(b)
+        };
+        let bounds = [
+            ("temperature", s.temperature),
+            ("top_p", s.top_p),
+            ("top_k", s.top_k),
+            ("num_ctx", capped(s.num_ctx, cfg.max_num_ctx as f64)),
+            (
+                "num_predict",
+                capped(s.num_predict, cfg.max_num_predict as f64),
+            ),
+            ("num_gpu", s.num_gpu),
+            ("num_thread", s.num_thread),
+        ]
+        .into_iter()
+        .filter_map(|(name, b)| Some((name, b?)))
+        .collect();
+        Self {
+            mode: s.mode,
+            deny: &s.deny,
+            bounds,
+        }
+    }
+
+    /// Check the sampling parameters of a request, both in Ollama's
+    /// `options` and at the top level (OpenAI style). In clamp mode
+    /// offending values are rewritten and denied options dropped; returns
+    /// whether the document changed.
+    pub fn enforce(&self, doc: &mut Value) -> Result<bool, Violation> {
+        let Some(obj) = doc.as_object_mut() else {
+            return Ok(false);
+        };
+        let mut changed = self.enforce_map(obj, "", true)?;
+        if let Some(options) = obj.get_mut("options").and_then(Value::as_object_mut) {
+            changed |= self.enforce_map(options, "options.", false)?;
+        }
+        Ok(changed)
+    }
+
+    fn enforce_map(
+        &self,
+        map: &mut Map<String, Value>,
+        prefix: &str,
+        top_level: bool,
+    ) -> Result<bool, Violation> {
+        let clamp = self.mode == SamplingMode::Clamp;
+        let mut changed = false;
+        for key in map.keys().cloned().collect::<Vec<_>>() {
+            let option = if top_level && NUM_PREDICT_ALIASES.contains(&key.as_str()) {
+                "num_predict"
+            } else {
+                key.as_str()
+            };
+            if self.deny.iter().any(|d| d == option) {
+                if !clamp {
+                    return Err(Violation {
+                        reason: "option_denied",
+                        message: format!("{}{} is not allowed", prefix, key),
+                    });
+                }
+                map.remove(&key);
+                changed = true;
+                continue;
+            }
+            let Some((option, bound)) = self.bounds.iter().find(|(name, _)| *name == option) else {
+                continue;
+            };
+            let Some(value) = map.get(&key).and_then(Value::as_f64) else {
+                continue;
+            };
+            let Some(fixed) = check(option, *bound, value) else {
+                continue;
+            };
+            if !clamp {
+                let too_high = fixed < value || (*option == "num_predict" && value < 0.0);
+                let reason = match (too_high, *option) {
+                    (true, "num_ctx") => "num_ctx_too_high",
+                    (true, "num_predict") => "num_predict_too_high",
+                    _ => "option_out_of_range",
+                };
+                let limit = if too_high { "at most" } else { "at least" };
+                return Err(Violation {
+                    reason,
+                    message: format!("{}{} must be {} {}", prefix, key, limit, fixed),
+                });
+            }
+            let fixed = if INTEGER_OPTIONS.contains(option) {
+                Value::from(fixed as i64)
+            } else {
+                Value::from(fixed)
+            };
+            map.insert(key, fixed);
+            changed = true;
+        }
+        Ok(changed)
+    }
+}
+
+/// The in-range value for `value`, or `None` if it is already fine.
+/// A negative `num_predict` means "no limit", so it counts as too high.
+fn check(option: &str, bound: Bound, value: f64) -> Option<f64> {
+    if option == "num_predict" && value < 0.0 {
+        return bound.max;
+    }
+    if let Some(min) = bound.min.filter(|min| value < *min) {
+        return Some(min);
+    }
+    bound.max.filter(|max| value > *max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(yaml: &str) -> ProxyConfig {
+        let mut cfg = ProxyConfig {
+            max_num_ctx: 8192,
+            ..ProxyConfig::default()
+        };
+        cfg.sampling = serde_yaml::from_str(yaml).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn test_rejects_nested_and_openai_fields() {
+        let cfg = policy("{ deny: [num_gpu], temperature: { min: 0, max: 1 } }");
+        let limits = SamplingLimits::new(&cfg);
+        let err = |mut doc: Value| limits.enforce(&mut doc).unwrap_err();
+
+        let v = err(json!({"options": {"num_ctx": 65536}}));
+        assert_eq!(v.reason, "num_ctx_too_high");
+        assert_eq!(v.message, "options.num_ctx must be at most 8192");
+        assert_eq!(err(json!({"num_ctx": 9000})).reason, "num_ctx_too_high");
+        assert_eq!(
+            err(json!({"max_tokens": 100000})).reason,
+            "num_predict_too_high"
+        );
+        // -1 asks Ollama for unlimited output.
+        assert_eq!(
+            err(json!({"options": {"num_predict": -1}})).reason,
+            "num_predict_too_high"
+        );
+        assert_eq!(
+            err(json!({"temperature": -0.5})).message,
+            "temperature must be at least 0"
+        );
+        assert_eq!(
+            err(json!({"options": {"num_gpu": 99}})).reason,
+            "option_denied"
+        );
+        let mut ok = json!({"options": {"temperature": 0.7, "num_ctx": 4096}});
+        assert_eq!(limits.enforce(&mut ok), Ok(false));
+    }
+
+    #[test]
+    fn test_clamp_mode_rewrites() {
+        let cfg = policy(
+            "{ mode: clamp, deny: [num_gpu], top_k: { max: 40 }, num_predict: { max: 512 } }",
+        );
+        let limits = SamplingLimits::new(&cfg);
+        let mut doc = json!({
+            "max_completion_tokens": 2048,
+            "options": {"top_k": 100.0, "num_ctx": 100000, "num_gpu": 1, "num_predict": -1}
+        });
+        assert_eq!(limits.enforce(&mut doc), Ok(true));
+        assert_eq!(
+            doc,
+            json!({
+                "max_completion_tokens": 512,
+                "options": {"top_k": 40, "num_ctx": 8192, "num_predict": 512}
+            })
+        );
+    }
+}
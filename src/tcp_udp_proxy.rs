@@ -29,9 +29,11 @@ pub async fn run_tcp_gateway(
             if let Some(backend) = registry.pick_one(&service) {
                 match TcpStream::connect(&backend).await {
                     Ok(mut outbound) => {
+                        registry.report(&backend, true);
                         let _ = copy_bidirectional(&mut inbound, &mut outbound).await;
                     }
                     Err(err) => {
+                        registry.report(&backend, false);
                         eprintln!("[tcp] Failed to connect to backend {}: {}", backend, err);
                     }
                 }
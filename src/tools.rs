@@ -0,0 +1,322 @@
+// src/tools.rs
+
+use crate::{
+    config::{ToolCallAction, ToolPolicy},
+    response_stream::BodyRewrite,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ToolError {
+    #[error("tool '{0}' is not allowed")]
+    NotAllowed(String),
+    #[error("too many tools: {count} (limit {limit})")]
+    TooMany { count: usize, limit: usize },
+    #[error("tool '{name}' definition is {size} bytes (limit {limit})")]
+    SchemaTooLarge {
+        name: String,
+        size: usize,
+        limit: usize,
+    },
+    #[error("model called tool '{0}', which was not offered or is not allowed")]
+    Call(String),
+}
+
+impl ToolError {
+    /// Label for the policy rejection counter.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ToolError::NotAllowed(_) => "tool_not_allowed",
+            ToolError::TooMany { .. } => "too_many_tools",
+            ToolError::SchemaTooLarge { .. } => "tool_schema_too_large",
+            ToolError::Call(_) => "tool_call_blocked",
+        }
+    }
+}
+
+fn matches_any(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|p| match p.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == p,
+    })
+}
+
+fn allowed(policy: &ToolPolicy, name: &str) -> bool {
+    !matches_any(&policy.deny, name)
+        && (policy.allow.is_empty() || matches_any(&policy.allow, name))
+}
+
+/// Check the `tools` offered by a chat request. Returns their names.
+pub fn check_request(policy: &ToolPolicy, doc: &Value) -> Result<Vec<String>, ToolError> {
+    let Some(tools) = doc.get("tools").and_then(Value::as_array) else {
+        return Ok(Vec::new());
+    };
+    if let Some(limit) = policy.max_tools.filter(|l| tools.len() > *l) {
+        return Err(ToolError::TooMany {
+            count: tools.len(),
+            limit,
+        });
+    }
+    let mut names = Vec::with_capacity(tools.len());
+    for tool in tools {
+        let name = tool
+            .pointer("/function/name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if !allowed(policy, name) {
+            return Err(ToolError::NotAllowed(name.to_string()));
+        }
+        if let Some(limit) = policy.max_schema_bytes {
+            let size = serde_json::to_vec(tool).map(|v| v.len()).unwrap_or(0);
+            if size > limit {
+                return Err(ToolError::SchemaTooLarge {
+                    name: name.to_string(),
+                    size,
+                    limit,
+                });
+            }
+        }
+        names.push(name.to_string());
+    }
+    Ok(names)
+}
+
+/// Checks the `tool_calls` in a chat response against the tools the request
+/// offered (which already passed the policy). Handles a whole JSON
+/// response, Ollama NDJSON lines and OpenAI SSE deltas, where a call's name
+/// arrives once and later argument pieces only carry its index.
+pub struct ToolCallFilter {
+    block: bool,
+    offered: Vec<String>,
+    stream: bool,
+    buf: Vec<u8>,
+    decisions: HashMap<u64, bool>,
+    kept: bool,
+    stripped: bool,
+    blocked: bool,
+}
+
+impl ToolCallFilter {
+    /// `None` when responses are passed through unchecked.
+    pub fn new(policy: &ToolPolicy, offered: Vec<String>, stream: bool) -> Option<Self> {
+        (policy.calls != ToolCallAction::Pass).then(|| Self {
+            block: policy.calls == ToolCallAction::Block,
+            offered,
+            stream,
+            buf: Vec::new(),
+            decisions: HashMap::new(),
+            kept: false,
+            stripped: false,
+            blocked: false,
+        })
+    }
+
+    pub fn streaming(&self) -> bool {
+        self.stream
+    }
+
+    /// Check a complete response body. Returns the rewritten body if calls
+    /// were stripped.
+    pub fn filter_body(&mut self, body: &[u8]) -> Result<Option<Vec<u8>>, ToolError> {
+        let Ok(mut doc) = serde_json::from_slice::<Value>(body) else {
+            return Ok(None);
+        };
+        Ok(self
+            .filter(&mut doc)?
+            .then(|| serde_json::to_vec(&doc).unwrap_or_default()))
+    }
+
+    fn filter(&mut self, doc: &mut Value) -> Result<bool, ToolError> {
+        let mut changed = false;
+        if let Some(message) = doc.get_mut("message") {
+            changed |= self.filter_calls(message)?;
+        }
+        for choice in doc
+            .get_mut("choices")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+        {
+            for key in ["message", "delta"] {
+                if let Some(m) = choice.get_mut(key) {
+                    changed |= self.filter_calls(m)?;
+                }
+            }
+            // Nothing left to call: the model simply stopped.
+            if self.stripped && !self.kept && choice["finish_reason"] == "tool_calls" {
+                choice["finish_reason"] = json!("stop");
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Drop the disallowed entries of `message.tool_calls`.
+    fn filter_calls(&mut self, message: &mut Value) -> Result<bool, ToolError> {
+        let Some(calls) = message.get_mut("tool_calls").and_then(Value::as_array_mut) else {
+            return Ok(false);
+        };
+        let before = calls.len();
+        let mut rejected = None;
+        calls.retain(|call| {
+            let index = call.get("index").and_then(Value::as_u64);
+            match (
+                call.pointer("/function/name").and_then(Value::as_str),
+                index,
+            ) {
+                (Some(name), _) => {
+                    let ok = self.offered.iter().any(|o| o == name);
+                    if let Some(i) = index {
+                        self.decisions.insert(i, ok);
+                    }
+                    if !ok && rejected.is_none() {
+                        rejected = Some(name.to_string());
+                    }
+                    ok
+                }
+                (None, Some(i)) => self.decisions.get(&i).copied().unwrap_or(true),
+                (None, None) => true,
+            }
+        });
+        if let Some(name) = rejected.filter(|_| self.block) {
+            return Err(ToolError::Call(name));
+        }
+        self.kept |= !calls.is_empty();
+        if calls.len() == before {
+            return Ok(false);
+        }
+        self.stripped = true;
+        if calls.is_empty() {
+            if let Some(m) = message.as_object_mut() {
+                m.remove("tool_calls");
+            }
+        }
+        Ok(true)
+    }
+
+    fn line(&mut self, line: &[u8], out: &mut Vec<u8>) {
+        let sse = line.starts_with(b"data:");
+        let payload = if sse { &line[5..] } else { line };
+        let Ok(mut doc) = serde_json::from_slice::<Value>(payload) else {
+            // Blank lines, `event:` lines and `data: [DONE]`
+            out.extend_from_slice(line);
+            return;
+        };
+        match self.filter(&mut doc) {
+            Ok(false) => out.extend_from_slice(line),
+            Ok(true) => {
+                if sse {
+                    out.extend_from_slice(b"data: ");
+                }
+                out.extend_from_slice(&serde_json::to_vec(&doc).unwrap_or_default());
+                out.push(b'\n');
+            }
+            Err(e) => {
+                self.blocked = true;
+                let error = if sse {
+                    format!(
+                        "data: {}\n\n",
+                        json!({"error": {"message": e.to_string(), "type": e.reason()}})
+                    )
+                } else {
+                    format!("{}\n", json!({ "error": e.to_string() }))
+                };
+                out.extend_from_slice(error.as_bytes());
+            }
+        }
+    }
+}
+
+impl BodyRewrite for ToolCallFilter {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.buf.extend_from_slice(chunk);
+        let mut out = Vec::new();
+        while let Some(i) = self.buf.iter().position(|&b| b == b'\n') {
+            if self.blocked {
+                break;
+            }
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            self.line(&line, &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let rest = std::mem::take(&mut self.buf);
+        let mut out = Vec::new();
+        if !self.blocked && !rest.is_empty() {
+            self.line(&rest, &mut out);
+        }
+        out
+    }
+
+    fn done(&self) -> bool {
+        self.blocked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(yaml: &str) -> ToolPolicy {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn tool(name: &str) -> Value {
+        json!({"type": "function", "function": {"name": name, "parameters": {"type": "object"}}})
+    }
+
+    #[test]
+    fn test_request_policy() {
+        let p = policy(
+            "{ allow: [search, 'fs_*'], deny: [fs_delete], max_tools: 2, max_schema_bytes: 200 }",
+        );
+        let doc = json!({"tools": [tool("search"), tool("fs_read")]});
+        assert_eq!(check_request(&p, &doc).unwrap(), ["search", "fs_read"]);
+        let err = |tools: Vec<Value>| check_request(&p, &json!({ "tools": tools })).unwrap_err();
+        assert_eq!(
+            err(vec![tool("fs_delete")]),
+            ToolError::NotAllowed("fs_delete".into())
+        );
+        assert_eq!(err(vec![tool("shell")]).reason(), "tool_not_allowed");
+        assert_eq!(
+            err(vec![tool("search"); 3]),
+            ToolError::TooMany { count: 3, limit: 2 }
+        );
+        let mut big = tool("search");
+        big["function"]["description"] = json!("x".repeat(300));
+        assert_eq!(err(vec![big]).reason(), "tool_schema_too_large");
+    }
+
+    #[test]
+    fn test_strips_and_blocks_calls() {
+        let strip = policy("{ calls: strip }");
+        let mut f = ToolCallFilter::new(&strip, vec!["search".into()], false).unwrap();
+        let body = br#"{"choices":[{"message":{"role":"assistant","tool_calls":[
+            {"id":"1","function":{"name":"shell","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#;
+        let out: Value = serde_json::from_slice(&f.filter_body(body).unwrap().unwrap()).unwrap();
+        assert!(out["choices"][0]["message"].get("tool_calls").is_none());
+        assert_eq!(out["choices"][0]["finish_reason"], "stop");
+
+        // OpenAI SSE: the name comes once, argument pieces follow by index.
+        let mut f = ToolCallFilter::new(&strip, vec!["search".into()], true).unwrap();
+        let mut out = f.feed(b"data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"name\":\"search\",\"arguments\":\"\"}},{\"index\":1,\"function\":{\"name\":\"shell\",\"arguments\":\"\"}}]}}]}\n\n");
+        out.extend(f.feed(b"data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":1,\"function\":{\"arguments\":\"rm\"}}]}}]}\n\ndata: [DONE]\n\n"));
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("search") && !text.contains("shell") && !text.contains("rm"));
+        assert!(text.ends_with("data: [DONE]\n\n"));
+
+        // Ollama NDJSON with blocking: an error line ends the stream.
+        let block = policy("{ calls: block }");
+        let mut f = ToolCallFilter::new(&block, vec![], true).unwrap();
+        let out = f.feed(b"{\"message\":{\"content\":\"hi\"},\"done\":false}\n{\"message\":{\"tool_calls\":[{\"function\":{\"name\":\"shell\",\"arguments\":{}}}]},\"done\":false}\n{\"done\":true}\n");
+        let lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains("model called tool 'shell'"));
+        assert!(f.done());
+        assert!(ToolCallFilter::new(&ToolPolicy::default(), vec![], true).is_none());
+    }
+}
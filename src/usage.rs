@@ -0,0 +1,198 @@
+// src/usage.rs
+
+use crate::response_stream::BodyRewrite;
+use serde_json::Value;
+
+/// Token counts reported by the model server for one request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Read Ollama's `prompt_eval_count`/`eval_count` or an OpenAI-style
+    /// `usage` object from one response document.
+    pub fn from_json(v: &Value) -> Option<Self> {
+        let count = |v: &Value, k: &str| v.get(k).and_then(Value::as_u64);
+        if v.get("eval_count").is_some() || v.get("prompt_eval_count").is_some() {
+            return Some(Self {
+                prompt_tokens: count(v, "prompt_eval_count").unwrap_or(0),
+                completion_tokens: count(v, "eval_count").unwrap_or(0),
+            });
+        }
+        let u = v.get("usage").filter(|u| u.is_object())?;
+        let prompt = count(u, "prompt_tokens").unwrap_or(0);
+        let completion = count(u, "completion_tokens")
+            .or_else(|| count(u, "total_tokens").map(|t| t.saturating_sub(prompt)))
+            .unwrap_or(0);
+        Some(Self {
+            prompt_tokens: prompt,
+            completion_tokens: completion,
+        })
+    }
+}
+
+/// Longest single line we keep around while looking for usage fields.
+const MAX_LINE: usize = 1 << 20;
+
+/// Finds token usage in a response body as it streams past.
+///
+/// Works line by line so it handles Ollama NDJSON (usage on the final
+/// `"done": true` line), SSE `data:` events, and single-document JSON.
+/// The last usage seen wins. If a stream is cut off before the final
+/// usage line, each streamed line is counted as one completion token.
+#[derive(Debug, Default)]
+pub struct UsageScanner {
+    line: Vec<u8>,
+    overflow: bool,
+    lines: u64,
+    found: Option<TokenUsage>,
+}
+
+impl UsageScanner {
+    pub fn feed(&mut self, chunk: &[u8]) {
+        let mut rest = chunk;
+        while let Some(i) = rest.iter().position(|&b| b == b'\n') {
+            self.push(&rest[..i]);
+            self.end_line();
+            rest = &rest[i + 1..];
+        }
+        self.push(rest);
+    }
+
+    pub fn finish(mut self) -> Option<TokenUsage> {
+        self.end_line();
+        self.found.or((self.lines > 1).then_some(TokenUsage {
+            prompt_tokens: 0,
+            completion_tokens: self.lines,
+        }))
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        if self.line.len() + bytes.len() > MAX_LINE {
+            self.overflow = true;
+            self.line.clear();
+        } else if !self.overflow {
+            self.line.extend_from_slice(bytes);
+        }
+    }
+
+    fn end_line(&mut self) {
+        let blank = self.line.iter().all(u8::is_ascii_whitespace);
+        if !blank && !self.line.starts_with(b"data: [DONE]") {
+            self.lines += 1;
+        }
+        if !self.overflow {
+            if let Some(u) = parse_line(&self.line) {
+                self.found = Some(u);
+            }
+        }
+        self.line.clear();
+        self.overflow = false;
+    }
+}
+
+/// Drops the usage-only `data:` events (`"choices": []`) from an
+/// OpenAI-style event stream, for clients that did not ask for them.
+#[derive(Debug, Default)]
+pub struct UsageEventFilter {
+    line: Vec<u8>,
+    /// The next blank line ends a dropped event
+    dropping: bool,
+}
+
+impl UsageEventFilter {
+    fn keep(&mut self, line: &[u8]) -> bool {
+        let text = line.trim_ascii();
+        if std::mem::take(&mut self.dropping) && text.is_empty() {
+            return false;
+        }
+        let Some(data) = text.strip_prefix(b"data:") else {
+            return true;
+        };
+        let usage_only = serde_json::from_slice::<Value>(data).is_ok_and(|v| {
+            v.get("choices")
+                .and_then(Value::as_array)
+                .is_some_and(Vec::is_empty)
+                && v.get("usage").is_some_and(Value::is_object)
+        });
+        self.dropping = usage_only;
+        !usage_only
+    }
+}
+
+impl BodyRewrite for UsageEventFilter {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(chunk.len());
+        let mut rest = chunk;
+        while let Some(i) = rest.iter().position(|&b| b == b'\n') {
+            self.line.extend_from_slice(&rest[..=i]);
+            let line = std::mem::take(&mut self.line);
+            if self.keep(&line) {
+                out.extend_from_slice(&line);
+            }
+            rest = &rest[i + 1..];
+        }
+        self.line.extend_from_slice(rest);
+        out
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.line)
+    }
+}
+
+fn parse_line(line: &[u8]) -> Option<TokenUsage> {
+    let line = line.strip_prefix(b"data:").unwrap_or(line);
+    let text = std::str::from_utf8(line).ok()?.trim();
+    if !(text.contains("eval_count") || text.contains("\"usage\"")) {
+        return None;
+    }
+    let v: Value = serde_json::from_str(text).ok()?;
+    TokenUsage::from_json(&v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scans_ndjson_sse_and_json() {
+        let mut s = UsageScanner::default();
+        s.feed(b"{\"response\":\"a\",\"done\":false}\n{\"done\":true,\"prompt_e");
+        s.feed(b"val_count\":12,\"eval_count\":30}\n");
+        assert_eq!(s.finish().map(|u| u.total()), Some(42));
+
+        let mut s = UsageScanner::default();
+        s.feed(b"data: {\"choices\":[]}\n\ndata: {\"choices\":[],\"usage\":");
+        s.feed(b"{\"prompt_tokens\":5,\"completion_tokens\":7}}\n\ndata: [DONE]\n\n");
+        assert_eq!(s.finish().map(|u| u.total()), Some(12));
+
+        let mut s = UsageScanner::default();
+        s.feed(br#"{"object":"list","usage":{"prompt_tokens":9,"total_tokens":9}}"#);
+        let u = s.finish().unwrap();
+        assert_eq!((u.prompt_tokens, u.completion_tokens), (9, 0));
+
+        // Client went away before the final line: estimate from chunks.
+        let mut s = UsageScanner::default();
+        s.feed(b"{\"response\":\"a\"}\n{\"response\":\"b\"}\n{\"resp");
+        assert_eq!(s.finish().map(|u| u.completion_tokens), Some(3));
+    }
+
+    #[test]
+    fn test_usage_event_filter_drops_usage_only_events() {
+        let mut f = UsageEventFilter::default();
+        let mut out = f.feed(b"data: {\"choices\":[{\"delta\":{}}]}\n\ndata: {\"choices\":[],");
+        out.extend(f.feed(b"\"usage\":{\"prompt_tokens\":5}}\n\ndata: [DONE]\n\n"));
+        out.extend(f.finish());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "data: {\"choices\":[{\"delta\":{}}]}\n\ndata: [DONE]\n\n"
+        );
+    }
+}
@@ -0,0 +1,511 @@
+// src/validate.rs
+
+use crate::{config::Config, health};
+use std::{collections::HashMap, fmt, path::Path};
+
+/// One problem in a config file, located by its YAML path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    /// e.g. `backends[1].protocol`; empty for the file as a whole
+    pub path: String,
+    /// 1-based, when the path could be found in the text
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Parse a config file strictly and check it makes sense. Returns every
+/// problem found: each bad key is reported and dropped so the rest can be
+/// checked, until one cannot be dropped (e.g. a required key, or an item of
+/// a list); semantic problems follow once the structure is sound.
+pub fn parse(text: &str) -> Result<Config, Vec<Issue>> {
+    let mut doc: serde_yaml::Value = serde_yaml::from_str(text).map_err(|e| {
+        vec![Issue {
+            path: String::new(),
+            line: e.location().map(|l| l.line()),
+            message: e.to_string(),
+        }]
+    })?;
+    let mut issues = Vec::new();
+    let mut removed = Vec::new();
+    let cfg = loop {
+        match serde_path_to_error::deserialize::<_, Config>(doc.clone()) {
+            Ok(cfg) => break Some(cfg),
+            Err(e) => {
+                let path = e.path().to_string();
+                let path = if path == "." { String::new() } else { path };
+                let message = e.inner().to_string();
+                // A required key dropped below; it has been reported.
+                if let Some(field) = missing_field(&message) {
+                    let full = match path.as_str() {
+                        "" => field.to_string(),
+                        parent => format!("{}.{}", parent, field),
+                    };
+                    if removed.contains(&full) {
+                        break None;
+                    }
+                }
+                // Serde lists every expected field; the key alone reads better.
+                let message = match unknown_field(&message) {
+                    Some(key) => format!("unknown field `{}`", key),
+                    None => message,
+                };
+                issues.push(Issue {
+                    line: locate(text, &path),
+                    path: path.clone(),
+                    message,
+                });
+                // Drop the key and carry on to find the next problem.
+                if !remove(&mut doc, &path) {
+                    break None;
+                }
+                removed.push(path);
+            }
+        }
+    };
+    // Serde does not always visit keys in file order.
+    issues.sort_by_key(|i| i.line);
+    // With the bad keys dropped the rest can still be checked.
+    if let Some(cfg) = cfg {
+        issues.extend(check(&cfg).into_iter().map(|(path, message)| Issue {
+            line: locate(text, &path),
+            path,
+            message,
+        }));
+        if issues.is_empty() {
+            return Ok(cfg);
+        }
+    }
+    Err(issues)
+}
+
+/// The key of a serde "unknown field `x`, expected ..." error.
+fn unknown_field(message: &str) -> Option<&str> {
+    let rest = message.strip_prefix("unknown field `")?;
+    rest.split_once('`').map(|(key, _)| key)
+}
+
+/// The key of a serde "missing field `x`" error.
+fn missing_field(message: &str) -> Option<&str> {
+    let rest = message.strip_prefix("missing field `")?;
+    rest.split_once('`').map(|(key, _)| key)
+}
+
+/// Remove the key `path` ends with from its mapping. Returns whether
+/// anything was removed; list items are left alone, as dropping one would
+/// shift the paths of those after it.
+fn remove(doc: &mut serde_yaml::Value, path: &str) -> bool {
+    let segments = segments(path);
+    let Some((Segment::Key(key), parents)) = segments.split_last() else {
+        return false;
+    };
+    let mut node = doc;
+    for segment in parents {
+        let next = match segment {
+            Segment::Key(k) => node.get_mut(*k),
+            Segment::Index(i) => node.get_mut(*i),
+        };
+        match next {
+            Some(next) => node = next,
+            None => return false,
+        }
+    }
+    node.as_mapping_mut().and_then(|m| m.remove(key)).is_some()
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// `backends[1].protocol` -> `backends`, `1`, `protocol`
+fn segments(path: &str) -> Vec<Segment<'_>> {
+    let mut out = Vec::new();
+    for part in path.split('.').filter(|p| !p.is_empty()) {
+        let (key, indices) = part.split_once('[').unwrap_or((part, ""));
+        if !key.is_empty() {
+            out.push(Segment::Key(key));
+        }
+        out.extend(
+            indices
+                .split(['[', ']'])
+                .filter_map(|i| i.parse().ok())
+                .map(Segment::Index),
+        );
+    }
+    out
+}
+
+/// A line of block-style YAML: where its content starts, where each `- `
+/// item marker is, and the mapping key it holds, if any.
+struct Line<'a> {
+    indent: usize,
+    dashes: Vec<usize>,
+    key: Option<(usize, &'a str)>,
+}
+
+fn scan(line: &str) -> Option<Line<'_>> {
+    let indent = line.len() - line.trim_start().len();
+    let mut rest = line.trim_start();
+    if rest.is_empty() || rest.starts_with('#') || rest.starts_with("---") {
+        return None;
+    }
+    let mut col = indent;
+    let mut dashes = Vec::new();
+    while rest == "-" || rest.starts_with("- ") {
+        dashes.push(col);
+        let trimmed = rest[1..].trim_start();
+        col += rest.len() - trimmed.len();
+        rest = trimmed;
+    }
+    let key = rest
+        .split_once(':')
+        .filter(|(k, v)| !k.starts_with(['{', '[']) && (v.is_empty() || v.starts_with(' ')))
+        .map(|(k, _)| (col, k.trim().trim_matches(['"', '\''])));
+    Some(Line {
+        indent,
+        dashes,
+        key,
+    })
+}
+
+/// The line of `path` in block-style YAML, or of its closest ancestor that
+/// can be found (e.g. when the rest is written in flow style).
+fn locate(text: &str, path: &str) -> Option<usize> {
+    let lines: Vec<(usize, Line)> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(i, l)| scan(l).map(|l| (i, l)))
+        .collect();
+    // The lines still in scope, and the column their parent sits at.
+    let mut region = &lines[..];
+    let mut parent: Option<usize> = None;
+    let mut found = None;
+    let below = |col: usize, parent: Option<usize>| parent.is_none_or(|p| col > p);
+    for segment in segments(path) {
+        match segment {
+            Segment::Key(key) => {
+                let Some(level) = region
+                    .iter()
+                    .filter_map(|(_, l)| l.key.map(|(c, _)| c))
+                    .filter(|c| below(*c, parent))
+                    .min()
+                else {
+                    break;
+                };
+                let Some(at) = region.iter().position(|(_, l)| l.key == Some((level, key))) else {
+                    break;
+                };
+                let end = region[at + 1..]
+                    .iter()
+                    .position(|(_, l)| {
+                        l.indent < level || (l.indent == level && l.dashes.first() != Some(&level))
+                    })
+                    .map_or(region.len(), |n| at + 1 + n);
+                found = Some(region[at].0);
+                region = &region[at + 1..end];
+                parent = Some(level);
+            }
+            Segment::Index(index) => {
+                let Some(level) = region
+                    .iter()
+                    .flat_map(|(_, l)| l.dashes.iter().copied())
+                    .filter(|c| parent.is_none_or(|p| *c >= p))
+                    .min()
+                else {
+                    break;
+                };
+                let items: Vec<usize> = region
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, l))| l.dashes.contains(&level))
+                    .map(|(n, _)| n)
+                    .collect();
+                let Some(&at) = items.get(index) else {
+                    break;
+                };
+                let end = items.get(index + 1).copied().unwrap_or(region.len());
+                found = Some(region[at].0);
+                region = &region[at..end];
+                parent = Some(level);
+            }
+        }
+    }
+    found.map(|i| i + 1)
+}
+
+/// Semantic checks on a config that parsed, as `(path, message)`.
+fn check(cfg: &Config) -> Vec<(String, String)> {
+    let mut issues = Vec::new();
+
+    let listeners = [
+        ("http_bind_addr", cfg.http_addr()),
+        ("https_bind_addr", cfg.https_addr()),
+        ("grpc_bind_addr", cfg.grpc_addr()),
+    ];
+    for (path, addr) in &listeners {
+        if let Err(e) = addr {
+            issues.push((path.to_string(), format!("not an IP address: {}", e)));
+        }
+    }
+
+    // TCP listeners, then UDP; a port may be used once per protocol.
+    let tcp = [
+        ("http_port", Some(cfg.http_port)),
+        ("https_port", cfg.https_listen_port()),
+        ("grpc_port", Some(cfg.grpc_listen_port())),
+        ("tcp_port", cfg.tcp_port),
+    ];
+    if cfg.https_listen_port().is_none() {
+        issues.push((
+            "http_port".to_string(),
+            "set https_port: http_port + 1 is out of range".to_string(),
+        ));
+    }
+    let mut taken: HashMap<u16, &str> = HashMap::new();
+    for (path, port) in tcp {
+        let Some(port) = port else { continue };
+        match taken.get(&port) {
+            Some(other) => issues.push((
+                path.to_string(),
+                format!("port {} is also used by {}", port, other),
+            )),
+            None => {
+                taken.insert(port, path);
+            }
+        }
+    }
+
+    for (i, be) in cfg.backends.iter().enumerate() {
+        let problem = match be.protocol.as_str() {
+            "http" | "https" | "grpc" => url_problem(&be.address),
+            "tcp" | "udp" => host_port_problem(&be.address),
+            other => {
+                issues.push((
+                    format!("backends[{}].protocol", i),
+                    format!(
+                        "unknown protocol '{}', expected http, https, grpc, tcp or udp",
+                        other
+                    ),
+                ));
+                None
+            }
+        };
+        if let Some(problem) = problem {
+            issues.push((format!("backends[{}].address", i), problem));
+        }
+        if let Some(hc) = &be.health_check {
+            for (key, value) in [("interval", &hc.interval), ("timeout", &hc.timeout)] {
+                if let Err(e) = health::period(value) {
+                    issues.push((
+                        format!("backends[{}].health_check.{}", i, key),
+                        format!("'{}': {}", value, e),
+                    ));
+                }
+            }
+        }
+    }
+    if let Some(problem) = url_problem(&cfg.proxy.upstream) {
+        issues.push(("proxy.upstream".to_string(), problem));
+    }
+
+    for (path, file) in [
+        ("tls.cert_path", &cfg.tls.cert_path),
+        ("tls.key_path", &cfg.tls.key_path),
+    ] {
+        if !Path::new(file).is_file() {
+            issues.push((path.to_string(), format!("file '{}' not found", file)));
+        }
+    }
+
+    let lists = [
+        (
+            "proxy.endpoint_allowlist",
+            &cfg.proxy.endpoint_allowlist,
+            "proxy.endpoint_denylist",
+            &cfg.proxy.endpoint_denylist,
+        ),
+        (
+            "proxy.tools.allow",
+            &cfg.proxy.tools.allow,
+            "proxy.tools.deny",
+            &cfg.proxy.tools.deny,
+        ),
+    ];
+    for (allow_path, allow, deny_path, deny) in lists {
+        for (i, entry) in allow.iter().enumerate() {
+            if deny.contains(entry) {
+                issues.push((
+                    format!("{}[{}]", allow_path, i),
+                    format!("'{}' is also in {}", entry, deny_path),
+                ));
+            }
+        }
+    }
+    issues
+}
+
+fn url_problem(address: &str) -> Option<String> {
+    match reqwest::Url::parse(address) {
+        Ok(url) if url.host().is_some() => None,
+        Ok(_) => Some(format!("'{}' has no host", address)),
+        Err(e) => Some(format!("'{}' is not a URL: {}", address, e)),
+    }
+}
+
+fn host_port_problem(address: &str) -> Option<String> {
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => None,
+        _ => Some(format!("'{}' is not host:port", address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "http_port: 8080
+auth: {}
+tls:
+  cert_path: Cargo.toml
+  key_path: Cargo.toml
+backends:
+  - name: api
+    protocol: http
+    address: \"http://127.0.0.1:9000\"
+    routes: [\"/api\"]
+  - name: tcpservice
+    protocol: tcp
+    address: \"127.0.0.1:9100\"
+consul_url: \"\"
+tls_mode: file
+tls_domain: \"\"
+tls_email: \"\"
+rate_limit_per_sec: 0
+rate_limit_burst: 0
+";
+
+    #[test]
+    fn test_locates_paths() {
+        assert_eq!(locate(BASE, "tls.key_path"), Some(5));
+        assert_eq!(locate(BASE, "backends[1].protocol"), Some(12));
+        assert_eq!(locate(BASE, "backends[0]"), Some(7));
+        // Flow style: the closest ancestor.
+        assert_eq!(locate(BASE, "backends[0].routes[0]"), Some(10));
+        assert_eq!(locate(BASE, "auth.oidc_providers"), Some(2));
+        assert_eq!(locate(BASE, "missing"), None);
+    }
+
+    #[test]
+    fn test_reports_every_unknown_key() {
+        assert!(parse(BASE).is_ok());
+        let text = BASE.replace(
+            "tls_mode: file\n",
+            "tls_mode: file\ntls_cert_path: ./cert.pem\n",
+        );
+        let text = text.replace("    routes:", "    route:");
+        let issues = parse(&text).unwrap_err();
+        let found: Vec<_> = issues.iter().map(|i| (i.path.as_str(), i.line)).collect();
+        assert_eq!(
+            found,
+            [("backends[0].route", Some(10)), ("tls_cert_path", Some(16))]
+        );
+        assert!(issues[1]
+            .to_string()
+            .starts_with("line 16: tls_cert_path: unknown field `tls_cert_path`"));
+    }
+
+    #[test]
+    fn test_reports_type_errors_and_keeps_going() {
+        let text = BASE
+            .replace("http_port: 8080", "http_port: eighty")
+            .replace("    protocol: tcp", "    protocol: tcp\n    weight: 2")
+            .replace("rate_limit_per_sec: 0", "rate_limit_per_sec: -1")
+            + "cache: { backend: tape }\n";
+        let found: Vec<_> = parse(&text)
+            .unwrap_err()
+            .into_iter()
+            .map(|i| (i.path, i.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("http_port".to_string(), Some(1)),
+                ("backends[1].weight".to_string(), Some(13)),
+                ("rate_limit_per_sec".to_string(), Some(19)),
+                ("cache.backend".to_string(), Some(21)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_checks() {
+        let text = format!(
+            "{}https_port: 8080
+grpc_bind_addr: localhost
+proxy:
+  upstream: not a url
+  endpoint_allowlist: [/api/chat, /api/pull]
+",
+            BASE.replace("protocol: tcp", "protocol: sctp")
+                .replace("key_path: Cargo.toml", "key_path: missing.pem")
+        );
+        let issues: Vec<_> = parse(&text)
+            .unwrap_err()
+            .into_iter()
+            .map(|i| (i.path, i.line))
+            .collect();
+        assert_eq!(
+            issues,
+            [
+                ("grpc_bind_addr".to_string(), Some(21)),
+                ("https_port".to_string(), Some(20)),
+                ("backends[1].protocol".to_string(), Some(12)),
+                ("proxy.upstream".to_string(), Some(23)),
+                ("tls.key_path".to_string(), Some(5)),
+                ("proxy.endpoint_allowlist[1]".to_string(), Some(24)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_zero_health_check_periods() {
+        let text = BASE.replace(
+            "address: \"127.0.0.1:9100\"\n",
+            "address: \"127.0.0.1:9100\"\n    health_check: { interval: 10s, timeout: 0s }\n",
+        );
+        let issues = parse(&text).unwrap_err();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].path, "backends[1].health_check.timeout");
+        assert_eq!(issues[0].line, Some(14));
+    }
+
+    #[test]
+    fn test_semantic_checks_run_past_unknown_keys() {
+        let text = BASE.replace("    routes:", "    route:") + "https_port: 8080\n";
+        let found: Vec<_> = parse(&text)
+            .unwrap_err()
+            .into_iter()
+            .map(|i| (i.path, i.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("backends[0].route".to_string(), Some(10)),
+                ("https_port".to_string(), Some(20)),
+            ]
+        );
+    }
+}